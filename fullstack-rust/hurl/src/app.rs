@@ -1,11 +1,21 @@
 use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::fmt;
 use std::path::PathBuf;
+use std::time::Duration;
 use structopt::StructOpt;
 
-use crate::config;
+use crate::assertions::{self, Assertion};
+use crate::config::{self, Source};
 use crate::errors::{Error, HurlResult};
-use crate::session::make_safe_pathname;
+use crate::auth::{self, AuthType};
+use crate::export;
+use crate::filter::{self, Filter};
+use crate::secrets::{self, SecretStore};
+use crate::session;
 
 /// A command line HTTP client
 #[derive(StructOpt, Debug)]
@@ -28,19 +38,84 @@ pub struct App {
     /// Basic authentication.
     ///
     /// A string of the form `username:password`. If only
-    /// `username` is given then you will be prompted
-    /// for a password. If you wish to use no password
-    /// then use the form `username:`.
-    #[structopt(short, long)]
+    /// `username` is given then the password is taken from
+    /// the auth_command of the config file, from ~/.netrc
+    /// with --netrc, or else you will be prompted for it. If
+    /// you wish to use no password then use the form
+    /// `username:`.
+    #[structopt(short, long, env = "HURL_AUTH", hide_env_values = true)]
     pub auth: Option<String>,
 
     /// Bearer token authentication.
     ///
     /// A token which will be sent as "Bearaer <token>" in
     /// the authorization header.
-    #[structopt(short, long)]
+    #[structopt(short, long, env = "HURL_TOKEN", hide_env_values = true)]
     pub token: Option<String>,
 
+    /// Use the login for the host in ~/.netrc, or the file named by NETRC.
+    ///
+    /// Without --auth or --token the login of the machine is sent, and a
+    /// username given alone with --auth gets its password from there. The
+    /// file is never read without this option or `netrc = true` in the
+    /// configuration file.
+    #[structopt(long)]
+    pub netrc: bool,
+
+    /// How to send the credentials of --auth and --token.
+    ///
+    /// basic and bearer send --auth and --token as they are. digest sends
+    /// the request without credentials and answers the challenge of a 401
+    /// response with --auth. apikey sends --auth given as `header:key` in
+    /// that header, or a key alone as X-API-Key. sigv4 signs the request
+    /// the AWS way with --auth as `access-key-id:secret-access-key` and
+    /// --token as an optional session token. Without it --auth is sent as
+    /// basic and --token as bearer authentication.
+    #[structopt(long, possible_values = auth::AUTH_TYPES, parse(try_from_str = auth::parse_auth_type))]
+    pub auth_type: Option<AuthType>,
+
+    /// The AWS region to sign for with --auth-type sigv4, by default taken
+    /// from an amazonaws.com host.
+    #[structopt(long)]
+    pub aws_region: Option<String>,
+
+    /// The AWS service to sign for with --auth-type sigv4, by default taken
+    /// from an amazonaws.com host.
+    #[structopt(long)]
+    pub aws_service: Option<String>,
+
+    /// The token endpoint of an OAuth2 server, which turns on fetching
+    /// bearer tokens from it.
+    ///
+    /// A token is fetched with the grant given by --oauth-grant and kept in
+    /// the session along with its expiry and refresh token. It is refreshed
+    /// before it expires, and when a request gets a 401 response the token
+    /// is renewed and the request is sent once more. The URL may be relative
+    /// to the base_url of the profile.
+    #[structopt(long)]
+    pub oauth_token_url: Option<String>,
+
+    /// The OAuth2 client id.
+    #[structopt(long)]
+    pub oauth_client_id: Option<String>,
+
+    /// The OAuth2 client secret, sent with the client id as basic
+    /// authentication to the token endpoint.
+    #[structopt(long)]
+    pub oauth_client_secret: Option<String>,
+
+    /// The OAuth2 scopes to ask for, separated by spaces.
+    #[structopt(long)]
+    pub oauth_scope: Option<String>,
+
+    /// The OAuth2 grant used to fetch a token.
+    ///
+    /// The password grant takes the username and password from --auth,
+    /// which is then not sent with the request itself. Defaults to
+    /// client-credentials.
+    #[structopt(long, possible_values = &["client-credentials", "password"])]
+    pub oauth_grant: Option<String>,
+
     /// Session name.
     #[structopt(long)]
     pub session: Option<String>,
@@ -54,6 +129,31 @@ pub struct App {
     #[structopt(long)]
     pub read_only: bool,
 
+    /// Encrypt secrets stored in sessions with a key read from this file.
+    ///
+    /// The file may contain any data, it is only used to derive a key.
+    /// Encrypted `auth` and `token` values in the configuration file are
+    /// decrypted with the same key.
+    #[structopt(long, env = "HURL_KEY_FILE", parse(from_os_str))]
+    pub key_file: Option<PathBuf>,
+
+    /// Encrypt secrets stored in sessions with a passphrase.
+    ///
+    /// The passphrase is read from HURL_PASSPHRASE if it is set, otherwise
+    /// it is prompted for.
+    #[structopt(long)]
+    pub encrypt_secrets: bool,
+
+    /// Never write credentials to disk.
+    ///
+    /// Authentication, tokens and sensitive headers are used for this request
+    /// but left out when the session is saved.
+    #[structopt(long)]
+    pub no_store_secrets: bool,
+
+    #[structopt(skip)]
+    pub secrets: Option<SecretStore>,
+
     /// Default transport.
     ///
     /// If a URL is given without a transport, i.e example.com/foo
@@ -62,6 +162,267 @@ pub struct App {
     #[structopt(short, long)]
     pub secure: bool,
 
+    /// Exit with an error if the response has a 4xx or 5xx status.
+    ///
+    /// The exit code is 2 for transport errors, 3 for an error status and
+    /// 4 when any of the --expect-* checks fail.
+    #[structopt(long)]
+    pub check_status: bool,
+
+    /// Expected response status, e.g. `200`, `2xx` or `200,201`.
+    #[structopt(long, parse(try_from_str = assertions::parse_status))]
+    pub expect_status: Option<Assertion>,
+
+    /// Expected response header, either `Name` or `Name:value`.
+    #[structopt(long, number_of_values = 1, parse(try_from_str = assertions::parse_header))]
+    pub expect_header: Vec<Assertion>,
+
+    /// Expected value in a JSON response body, of the form `path [op value]`.
+    ///
+    /// The path selects a value such as `.data.items[0].id` and is written
+    /// as for --filter, so `[-1]` is the last element, `[1:3]` a slice and
+    /// `[]` every element. The op is one of ==, !=, <, <=, >, >=. Without
+    /// an op the path just has to exist.
+    ///
+    ///   e.g. --expect-json '.data.id == 42'
+    #[structopt(long, number_of_values = 1, parse(try_from_str = assertions::parse_json))]
+    pub expect_json: Vec<Assertion>,
+
+    /// Regular expression that the response body is expected to match.
+    #[structopt(long, number_of_values = 1, parse(try_from_str = assertions::parse_body))]
+    pub expect_body: Vec<Assertion>,
+
+    /// Save the response body to a file instead of printing it.
+    ///
+    /// The file name is taken from the Content-Disposition header of the
+    /// response or else from the last segment of the URL. Existing files are
+    /// not overwritten, a suffix such as `-1` is added instead.
+    #[structopt(short, long)]
+    pub download: bool,
+
+    /// File to save the response body to. Implies --download.
+    #[structopt(short, long, parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    /// Resume a partial download of the file given by --output.
+    #[structopt(long = "continue")]
+    pub resume: bool,
+
+    /// Write binary response bodies to a terminal as they are.
+    ///
+    /// By default a binary body is only shown as a hex dump when stdout is
+    /// a terminal, and written unchanged when it is redirected.
+    #[structopt(long)]
+    pub force_binary: bool,
+
+    /// The HTTP method to use, e.g. OPTIONS or PROPFIND.
+    ///
+    /// This overrides a method subcommand as well as the default of GET, or
+    /// POST when there is data to send. A method written in capitals can
+    /// also be given in place of a subcommand, e.g. `hurl OPTIONS example.com`.
+    #[structopt(short = "X", long, parse(try_from_str = parse_method))]
+    pub method: Option<reqwest::Method>,
+
+    /// Send this as the request body, or the contents of a file with `@file`.
+    ///
+    /// A body is also read from stdin when it is not a terminal. The
+    /// Content-Type is guessed from the file extension or the body itself
+    /// unless a Content-Type header is given. A raw body cannot be combined
+    /// with data parameters.
+    ///
+    ///   e.g. --raw '{"a":[1,2]}' or --raw @payload.xml
+    #[structopt(long)]
+    pub raw: Option<String>,
+
+    /// Do not read a request body from stdin.
+    #[structopt(long)]
+    pub ignore_stdin: bool,
+
+    /// Which parts of the exchange to print.
+    ///
+    /// Any combination of the letters
+    /// H: request headers
+    /// B: request body
+    /// h: response headers
+    /// b: response body
+    ///
+    /// The default is `hb`, or `HB` with --offline.
+    #[structopt(long, parse(try_from_str = parse_print))]
+    pub print: Option<Print>,
+
+    /// Print only the parts of a JSON response body picked by a jq-like
+    /// filter.
+    ///
+    /// A filter is a path such as `.items[0].name`, where `[]` or `.*`
+    /// takes every element, `[1:3]` a slice and `[-1]` the last element.
+    /// Filters can be joined with `|`, and `select(.price < 10)` keeps the
+    /// values for which a comparison with ==, !=, <, <=, > or >= holds.
+    ///
+    ///   e.g. --filter '.items[] | select(.stock > 0) | .name'
+    #[structopt(long, parse(try_from_str = filter::parse))]
+    pub filter: Option<Filter>,
+
+    /// Print strings picked by --filter without quotes.
+    #[structopt(long, requires = "filter")]
+    pub raw_output: bool,
+
+    /// How to print the response.
+    ///
+    /// text prints the parts chosen by --print. json prints one document
+    /// with the status, version, headers, body, timing and redirects of the
+    /// response, where the body is JSON if it parses as JSON, else a string,
+    /// or base64 if it is binary. The lookup, connection and handshake
+    /// times are only estimated with --timing. With run and import --run
+    /// each response is printed on a line of its own.
+    #[structopt(
+        long,
+        possible_values = OUTPUT_FORMATS,
+        parse(try_from_str = parse_output_format),
+        conflicts_with = "filter"
+    )]
+    pub output_format: Option<OutputFormat>,
+
+    /// When to colour the output.
+    ///
+    /// auto colours it when stdout is a terminal and NO_COLOR is not set.
+    #[structopt(long, possible_values = COLORS, parse(try_from_str = parse_color))]
+    pub color: Option<Color>,
+
+    /// Build and print the request without sending it.
+    #[structopt(long)]
+    pub offline: bool,
+
+    /// Print the request as a command or code snippet instead of sending it.
+    ///
+    /// The request is exported as it would be sent, with session headers,
+    /// authentication and parameters applied. The format is one of curl,
+    /// httpie, rust-reqwest or python-requests.
+    #[structopt(long, possible_values = export::FORMATS, parse(try_from_str = export::parse_format))]
+    pub export: Option<export::Format>,
+
+    /// Report how long the wait for the response and its transfer took,
+    /// along with estimates of the DNS lookup, connection and TLS handshake.
+    ///
+    /// The report goes to stderr. The client does not report the lookup,
+    /// connection or handshake of the request, so they are estimated by
+    /// timing a separate connection to the server made just before it. The
+    /// time to the first byte of the response includes them.
+    #[structopt(long)]
+    pub timing: bool,
+
+    /// Send the request this many times and print latency percentiles, the
+    /// statuses received and the throughput instead of the response.
+    #[structopt(long, parse(try_from_str = parse_count))]
+    pub repeat: Option<usize>,
+
+    /// The number of requests to have in flight at once with --repeat.
+    #[structopt(long, requires = "repeat", parse(try_from_str = parse_count))]
+    pub concurrency: Option<usize>,
+
+    /// Do not record this request in the history.
+    #[structopt(long)]
+    pub no_history: bool,
+
+    /// Record a raw request body in the history.
+    ///
+    /// It is left out by default, as it may hold passwords or tokens, and a
+    /// request sent again from the history is then sent without it.
+    #[structopt(long, conflicts_with = "no_history")]
+    pub history_body: bool,
+
+    /// Follow redirects.
+    #[structopt(short = "F", long)]
+    pub follow: bool,
+
+    /// The most redirects to follow with --follow. Defaults to 30.
+    #[structopt(long)]
+    pub max_redirects: Option<usize>,
+
+    /// Seconds to wait for the whole request, e.g. `30` or `2.5`.
+    #[structopt(long, parse(try_from_str = parse_seconds))]
+    pub timeout: Option<Duration>,
+
+    /// Seconds to wait for the connection to be established.
+    #[structopt(long, parse(try_from_str = parse_seconds))]
+    pub connect_timeout: Option<Duration>,
+
+    /// Send requests through this proxy, e.g. `http://localhost:3128`.
+    ///
+    /// Without this option the HTTP_PROXY and HTTPS_PROXY environment
+    /// variables are used.
+    #[structopt(long)]
+    pub proxy: Option<String>,
+
+    /// Trust the CA certificates in this PEM or DER file.
+    #[structopt(long, parse(from_os_str))]
+    pub cacert: Option<PathBuf>,
+
+    /// Client certificate for mutual TLS.
+    ///
+    /// Either a PEM file, with the private key given by --key or included
+    /// in the same file, or a PKCS#12 file ending in .p12 or .pfx whose
+    /// password is read from HURL_CERT_PASSWORD.
+    #[structopt(long, parse(from_os_str))]
+    pub cert: Option<PathBuf>,
+
+    /// Private key in PEM format for the certificate given by --cert.
+    #[structopt(long, parse(from_os_str))]
+    pub key: Option<PathBuf>,
+
+    /// Do not verify the certificate or host name of the server.
+    #[structopt(short = "k", long)]
+    pub insecure: bool,
+
+    /// Configuration profile to use.
+    ///
+    /// A profile is a table in the configuration file named like
+    /// `[profile.staging]` which may set the following keys:
+    /// base_url: string
+    /// headers: table of header names to values
+    /// auth: string
+    /// token: string
+    /// auth_command: string, a command printing the password for --auth
+    /// netrc: bool
+    /// auth_type: string
+    /// aws_region: string
+    /// aws_service: string
+    /// secure: bool
+    /// timeout: f64 (seconds)
+    /// connect_timeout: f64 (seconds)
+    /// follow: bool
+    /// max_redirects: usize
+    /// proxy: string
+    /// cacert: string
+    /// cert: string
+    /// key: string
+    /// insecure: bool
+    /// oauth_token_url: string
+    /// oauth_client_id: string
+    /// oauth_client_secret: string
+    /// oauth_scope: string
+    /// oauth_grant: string
+    ///
+    /// Values from the profile take precedence over those at the top level of
+    /// the file, but not over command line options. URLs starting with `/`,
+    /// e.g. `/users/1`, are resolved against the base_url of the profile.
+    /// The `default_profile` key of the file names a profile to use when
+    /// none is given.
+    #[structopt(long, env = "HURL_PROFILE")]
+    pub profile: Option<String>,
+
+    #[structopt(skip)]
+    pub base_url: Option<String>,
+
+    #[structopt(skip)]
+    pub auth_command: Option<String>,
+
+    #[structopt(skip)]
+    pub default_headers: Vec<(String, String)>,
+
+    /// Where each setting which is not a default came from.
+    #[structopt(skip)]
+    pub sources: BTreeMap<&'static str, Source>,
+
     /// Configuration file.
     ///
     /// A TOML file which is stored by default at HOME/.config/hurl/config
@@ -72,7 +433,12 @@ pub struct App {
     /// form: bool
     /// auth: string
     /// token: string
+    /// netrc: bool
     /// secure: bool
+    /// key_file: string
+    /// encrypt_secrets: bool
+    /// no_store_secrets: bool
+    /// default_profile: string
     ///
     /// Each option has the same meaning as the corresponding configuration
     /// option with the same name. The verbose setting is a number from 0
@@ -80,9 +446,11 @@ pub struct App {
     #[structopt(short, long, env = "HURL_CONFIG", parse(from_os_str))]
     pub config: Option<PathBuf>,
 
-    /// The HTTP Method to use, one of: HEAD, GET, POST, PUT, PATCH, DELETE.
+    /// The HTTP Method to use, one of: HEAD, GET, POST, PUT, PATCH, DELETE,
+    /// any other method in capitals such as OPTIONS, or a command such as
+    /// `run`.
     #[structopt(subcommand)]
-    pub cmd: Option<Method>,
+    pub cmd: Option<Command>,
 
     /// The URL to issue a request to if a method subcommand is not specified.
     pub url: Option<String>,
@@ -120,44 +488,366 @@ pub struct App {
     /// Raw JSON data from file -- key:=@filename
     ///
     ///   e.g. foo:=@bar.json becomes {"foo":{"bar":"this is from bar.json"}}
+    ///
+    /// The key of any data field may be a path which builds nested objects
+    /// and arrays, where `[]` appends to an array. Use `\.` or `\[` for a
+    /// literal dot or bracket in a key.
+    ///
+    ///   e.g. user[name]=x user[tags][]=a items[0][id]:=3 becomes
+    ///   {"items":[{"id":3}],"user":{"name":"x","tags":["a"]}}
+    ///
+    ///   user.name=x is the same as user[name]=x
     #[structopt(parse(try_from_str = parse_param))]
     pub parameters: Vec<Parameter>,
 }
 
 impl App {
+    /// Parse the command line like `from_args`, but also accept any method
+    /// token in capitals as a subcommand.
+    ///
+    /// Such a request is treated like a plain URL request with --method set,
+    /// so `hurl PROPFIND example.com` is the same as
+    /// `hurl --method PROPFIND example.com`.
+    pub fn parse_args() -> App {
+        let args: Vec<OsString> = std::env::args_os().collect();
+        let custom = custom_method(&args);
+        let mut clap = App::clap();
+        if let Some(name) = &custom {
+            trace!("Accepting {} as a method subcommand", name);
+            clap = clap.subcommand(MethodData::clap().name(name.as_str()));
+        }
+
+        let matches = clap.get_matches_from(&args);
+        let mut app = App::from_clap(&matches);
+        if let (name, Some(sub)) = matches.subcommand() {
+            if custom.as_deref() == Some(name) {
+                let data = MethodData::from_clap(sub);
+                if app.method.is_none() {
+                    app.method = reqwest::Method::from_bytes(name.as_bytes()).ok();
+                }
+                app.url = Some(data.url);
+                app.parameters = data.parameters;
+            }
+        }
+        app
+    }
+
     pub fn validate(&mut self) -> HurlResult<()> {
         if self.cmd.is_none() && self.url.is_none() {
             return Err(Error::MissingUrlAndCommand);
         }
+        if self.resume && self.output.is_none() {
+            return Err(Error::ResumeWithoutOutput);
+        }
+        if self.output.is_some() {
+            self.download = true;
+        }
+        if self.json_output() {
+            if self.repeat.is_some() {
+                return Err(Error::JsonOutputWith("--repeat"));
+            }
+            if self.offline {
+                return Err(Error::JsonOutputWith("--offline"));
+            }
+            if self.export.is_some() {
+                return Err(Error::JsonOutputWith("--export"));
+            }
+        }
         Ok(())
     }
 
-    pub fn process_config_file(&mut self) {
+    pub fn process_config_file(&mut self) -> HurlResult<()> {
+        self.record_command_line_sources();
         let config_path = config::config_file(self);
-        let config_opt = config::read_config_file(config_path);
-        if let Some(mut config) = config_opt {
-            if self.verbose == 0 {
-                if let Some(v) = config.verbose {
-                    self.verbose = v;
-                }
+        let mut config = match config::read_config_file(&config_path, self.config.is_some())? {
+            Some(config) => config,
+            None => {
+                return match &self.profile {
+                    Some(name) => Err(Error::UnknownProfile(name.clone())),
+                    None => Ok(()),
+                };
             }
-            if !self.form {
-                if let Some(f) = config.form {
-                    self.form = f;
-                }
+        };
+
+        if self.profile.is_none() {
+            if let Some(name) = config.default_profile.take() {
+                self.profile = Some(name);
+                self.sources.insert("profile", Source::File);
             }
-            if !self.secure {
-                if let Some(s) = config.secure {
-                    self.secure = s;
-                }
+        }
+        let mut profile = match &self.profile {
+            Some(name) => {
+                debug!("Using profile {}", name);
+                config
+                    .profile
+                    .remove(name)
+                    .ok_or_else(|| Error::UnknownProfile(name.clone()))?
+            }
+            None => config::Profile::default(),
+        };
+
+        if let Some(base_url) = profile.base_url.take() {
+            self.base_url = Some(base_url);
+            self.sources.insert("base_url", Source::Profile);
+        }
+        if !profile.headers.is_empty() {
+            self.default_headers = profile.headers.into_iter().collect();
+            self.sources.insert("headers", Source::Profile);
+        }
+        if self.timeout.is_none() {
+            if let Some(t) = profile.timeout {
+                self.timeout = Some(parse_seconds(&t.to_string())?);
+                self.sources.insert("timeout", Source::Profile);
+            }
+        }
+        if self.connect_timeout.is_none() {
+            if let Some(t) = profile.connect_timeout {
+                self.connect_timeout = Some(parse_seconds(&t.to_string())?);
+                self.sources.insert("connect_timeout", Source::Profile);
+            }
+        }
+        if !self.follow {
+            if let Some(f) = profile.follow {
+                self.follow = f;
+                self.sources.insert("follow", Source::Profile);
             }
-            if self.auth.is_none() {
-                self.auth = config.auth.take();
+        }
+        if self.max_redirects.is_none() {
+            if let Some(m) = profile.max_redirects {
+                self.max_redirects = Some(m);
+                self.sources.insert("max_redirects", Source::Profile);
+            }
+        }
+        if self.proxy.is_none() {
+            if let Some(p) = profile.proxy.take() {
+                self.proxy = Some(p);
+                self.sources.insert("proxy", Source::Profile);
+            }
+        }
+        if self.cacert.is_none() {
+            if let Some(c) = profile.cacert.take() {
+                self.cacert = Some(c);
+                self.sources.insert("cacert", Source::Profile);
             }
-            if self.token.is_none() {
-                self.token = config.token.take();
+        }
+        if self.cert.is_none() {
+            if let Some(c) = profile.cert.take() {
+                self.cert = Some(c);
+                self.sources.insert("cert", Source::Profile);
+            }
+        }
+        if self.key.is_none() {
+            if let Some(k) = profile.key.take() {
+                self.key = Some(k);
+                self.sources.insert("key", Source::Profile);
+            }
+        }
+        if !self.insecure {
+            if let Some(i) = profile.insecure {
+                self.insecure = i;
+                self.sources.insert("insecure", Source::Profile);
             }
         }
+        if self.oauth_token_url.is_none() {
+            if let Some(u) = profile.oauth_token_url.take() {
+                self.oauth_token_url = Some(u);
+                self.sources.insert("oauth_token_url", Source::Profile);
+            }
+        }
+        if self.oauth_client_id.is_none() {
+            if let Some(c) = profile.oauth_client_id.take() {
+                self.oauth_client_id = Some(c);
+                self.sources.insert("oauth_client_id", Source::Profile);
+            }
+        }
+        if self.oauth_client_secret.is_none() {
+            if let Some(s) = profile.oauth_client_secret.take() {
+                self.oauth_client_secret = Some(s);
+                self.sources.insert("oauth_client_secret", Source::Profile);
+            }
+        }
+        if self.oauth_scope.is_none() {
+            if let Some(s) = profile.oauth_scope.take() {
+                self.oauth_scope = Some(s);
+                self.sources.insert("oauth_scope", Source::Profile);
+            }
+        }
+        if self.oauth_grant.is_none() {
+            if let Some(g) = profile.oauth_grant.take() {
+                self.oauth_grant = Some(g);
+                self.sources.insert("oauth_grant", Source::Profile);
+            }
+        }
+
+        if self.verbose == 0 {
+            if let Some(v) = config.verbose {
+                self.verbose = v;
+                self.sources.insert("verbose", Source::File);
+            }
+        }
+        if !self.form {
+            if let Some(f) = config.form {
+                self.form = f;
+                self.sources.insert("form", Source::File);
+            }
+        }
+        if !self.secure {
+            if let Some((s, source)) = pick(profile.secure, config.secure) {
+                self.secure = s;
+                self.sources.insert("secure", source);
+            }
+        }
+        if self.auth.is_none() {
+            if let Some((a, source)) = pick(profile.auth.take(), config.auth.take()) {
+                self.auth = Some(a);
+                self.sources.insert("auth", source);
+            }
+        }
+        if self.token.is_none() {
+            if let Some((t, source)) = pick(profile.token.take(), config.token.take()) {
+                self.token = Some(t);
+                self.sources.insert("token", source);
+            }
+        }
+        if let Some((c, source)) = pick(profile.auth_command.take(), config.auth_command.take()) {
+            self.auth_command = Some(c);
+            self.sources.insert("auth_command", source);
+        }
+        if !self.netrc {
+            if let Some((n, source)) = pick(profile.netrc, config.netrc) {
+                self.netrc = n;
+                self.sources.insert("netrc", source);
+            }
+        }
+        if self.auth_type.is_none() {
+            if let Some(t) = profile.auth_type.take() {
+                self.auth_type = Some(t);
+                self.sources.insert("auth_type", Source::Profile);
+            }
+        }
+        if self.aws_region.is_none() {
+            if let Some(r) = profile.aws_region.take() {
+                self.aws_region = Some(r);
+                self.sources.insert("aws_region", Source::Profile);
+            }
+        }
+        if self.aws_service.is_none() {
+            if let Some(s) = profile.aws_service.take() {
+                self.aws_service = Some(s);
+                self.sources.insert("aws_service", Source::Profile);
+            }
+        }
+        if self.key_file.is_none() {
+            if let Some(k) = config.key_file.take() {
+                self.key_file = Some(k);
+                self.sources.insert("key_file", Source::File);
+            }
+        }
+        if !self.encrypt_secrets {
+            if let Some(e) = config.encrypt_secrets {
+                self.encrypt_secrets = e;
+                self.sources.insert("encrypt_secrets", Source::File);
+            }
+        }
+        if !self.no_store_secrets {
+            if let Some(n) = config.no_store_secrets {
+                self.no_store_secrets = n;
+                self.sources.insert("no_store_secrets", Source::File);
+            }
+        }
+        Ok(())
+    }
+
+    /// Note which settings were given on the command line or through an
+    /// environment variable before the configuration file is merged in.
+    fn record_command_line_sources(&mut self) {
+        fn os<T: AsRef<std::ffi::OsStr>>(value: &Option<T>) -> Option<OsString> {
+            value.as_ref().map(|v| v.as_ref().to_owned())
+        }
+        // A value which can also come from the environment only came from
+        // there if it is the same, as the command line takes precedence.
+        let given = [
+            (
+                "config",
+                self.config.is_some(),
+                Some(("HURL_CONFIG", os(&self.config))),
+            ),
+            (
+                "profile",
+                self.profile.is_some(),
+                Some(("HURL_PROFILE", os(&self.profile))),
+            ),
+            ("verbose", self.verbose > 0, None),
+            ("form", self.form, None),
+            ("secure", self.secure, None),
+            (
+                "auth",
+                self.auth.is_some(),
+                Some(("HURL_AUTH", os(&self.auth))),
+            ),
+            (
+                "token",
+                self.token.is_some(),
+                Some(("HURL_TOKEN", os(&self.token))),
+            ),
+            ("netrc", self.netrc, None),
+            ("auth_type", self.auth_type.is_some(), None),
+            ("aws_region", self.aws_region.is_some(), None),
+            ("aws_service", self.aws_service.is_some(), None),
+            (
+                "key_file",
+                self.key_file.is_some(),
+                Some(("HURL_KEY_FILE", os(&self.key_file))),
+            ),
+            ("encrypt_secrets", self.encrypt_secrets, None),
+            ("no_store_secrets", self.no_store_secrets, None),
+            ("timeout", self.timeout.is_some(), None),
+            ("connect_timeout", self.connect_timeout.is_some(), None),
+            ("follow", self.follow, None),
+            ("max_redirects", self.max_redirects.is_some(), None),
+            ("proxy", self.proxy.is_some(), None),
+            ("cacert", self.cacert.is_some(), None),
+            ("cert", self.cert.is_some(), None),
+            ("key", self.key.is_some(), None),
+            ("insecure", self.insecure, None),
+            ("oauth_token_url", self.oauth_token_url.is_some(), None),
+            ("oauth_client_id", self.oauth_client_id.is_some(), None),
+            (
+                "oauth_client_secret",
+                self.oauth_client_secret.is_some(),
+                None,
+            ),
+            ("oauth_scope", self.oauth_scope.is_some(), None),
+            ("oauth_grant", self.oauth_grant.is_some(), None),
+        ];
+        for (key, is_set, env) in given.iter() {
+            if !is_set {
+                continue;
+            }
+            let source = match env {
+                Some((var, value)) if std::env::var_os(var) == *value => Source::Environment,
+                _ => Source::CommandLine,
+            };
+            self.sources.insert(key, source);
+        }
+    }
+
+    /// Set up the secret store and decrypt any encrypted credentials.
+    pub fn unlock_secrets(&mut self) -> HurlResult<()> {
+        self.secrets = SecretStore::for_app(self)?;
+        if let Some(auth) = &self.auth {
+            self.auth = Some(secrets::reveal(self.secrets.as_ref(), auth)?);
+        }
+        if let Some(token) = &self.token {
+            self.token = Some(secrets::reveal(self.secrets.as_ref(), token)?);
+        }
+        if let Some(secret) = &self.oauth_client_secret {
+            self.oauth_client_secret = Some(secrets::reveal(self.secrets.as_ref(), secret)?);
+        }
+        for (_, value) in self.default_headers.iter_mut() {
+            *value = secrets::reveal(self.secrets.as_ref(), value)?;
+        }
+        Ok(())
     }
 
     pub fn log_level(&self) -> Option<&'static str> {
@@ -174,17 +864,302 @@ impl App {
         }
     }
 
-    pub fn host(&self) -> String {
+    /// The parts of the exchange to print, from --print or the default.
+    ///
+    /// Nothing is printed this way with --output-format json.
+    pub fn print(&self) -> Print {
+        match self.print {
+            _ if self.json_output() => Print::default(),
+            Some(print) => print,
+            None if self.offline => Print {
+                request_headers: true,
+                request_body: true,
+                ..Print::default()
+            },
+            None => Print {
+                response_headers: true,
+                response_body: true,
+                ..Print::default()
+            },
+        }
+    }
+
+    pub fn json_output(&self) -> bool {
+        self.output_format == Some(OutputFormat::Json)
+    }
+
+    /// Whether each response is printed as JSON on a line of its own, as
+    /// `run` and `import --run` send several requests.
+    pub fn json_lines(&self) -> bool {
+        self.json_output()
+            && matches!(self.cmd, Some(Command::Run(_)) | Some(Command::Import(_)))
+    }
+
+    /// Whether to colour the output, which by default is only done when
+    /// stdout is a terminal and NO_COLOR is not set.
+    pub fn color(&self) -> bool {
+        match self.color {
+            Some(Color::Always) => true,
+            Some(Color::Never) => false,
+            Some(Color::Auto) | None => {
+                atty::is(atty::Stream::Stdout) && std::env::var_os("NO_COLOR").is_none()
+            }
+        }
+    }
+
+    pub fn assertions(&self) -> Vec<&Assertion> {
+        self.expect_status
+            .iter()
+            .chain(self.expect_header.iter())
+            .chain(self.expect_json.iter())
+            .chain(self.expect_body.iter())
+            .collect()
+    }
+
+    /// The host the session for the request is stored under.
+    pub fn host(&self) -> HurlResult<String> {
+        session::host_key(self, self.target())
+    }
+
+    /// The URL as given on the command line.
+    pub fn target(&self) -> &str {
         if let Some(url) = &self.url {
-            make_safe_pathname(url)
-        } else if let Some(cmd) = &self.cmd {
-            make_safe_pathname(&cmd.data().url)
+            url
+        } else if let Some(Command::Method(method)) = &self.cmd {
+            &method.data().url
         } else {
             unreachable!();
         }
     }
 }
 
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// Run the requests stored in a request file.
+    #[structopt(name = "run")]
+    Run(RunData),
+    /// Encrypt a secret for use as `auth` or `token` in the configuration file.
+    ///
+    /// Requires --key-file or --encrypt-secrets. If no value is given then
+    /// you will be prompted for it.
+    #[structopt(name = "encrypt")]
+    Encrypt { value: Option<String> },
+    /// Check or show the configuration.
+    #[structopt(name = "config")]
+    Config(ConfigCommand),
+    /// Inspect and manage stored sessions.
+    #[structopt(name = "session")]
+    Session(SessionCommand),
+    /// Print a completion script for bash, zsh or fish.
+    ///
+    /// Besides options and subcommands, the script completes the names of
+    /// stored sessions for --session and of profiles for --profile.
+    ///
+    ///   e.g. hurl completions bash > /etc/bash_completion.d/hurl
+    #[structopt(name = "completions")]
+    Completions(CompletionsData),
+    /// Print the man page for hurl in roff format.
+    ///
+    ///   e.g. hurl man > /usr/local/share/man/man1/hurl.1
+    #[structopt(name = "man")]
+    Man,
+    /// Import requests from a curl command or a HAR file.
+    ///
+    /// The requests are printed as a request file for `hurl run`, or sent
+    /// right away with --run. With --session the headers and credentials of
+    /// each request are also stored in the session.
+    ///
+    ///   e.g. hurl import curl "curl -H 'Accept: text/plain' example.com" -o example.http
+    #[structopt(name = "import")]
+    Import(ImportCommand),
+    /// List and rerun the requests sent before.
+    ///
+    /// Every request sent is recorded along with its status, time taken and
+    /// response size, leaving out credentials and sensitive headers. The
+    /// last 1000 requests are kept.
+    #[structopt(name = "history")]
+    History(HistoryCommand),
+    #[structopt(flatten)]
+    Method(Method),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RunData {
+    /// The request file to run.
+    ///
+    /// A request file holds one or more requests separated by lines starting
+    /// with `###`. Each request is a method line, e.g. `GET example.com/users`,
+    /// followed by headers of the form `key: value`, a blank line, and an
+    /// optional body.
+    ///
+    /// Variables are defined with `@name = value` lines and used anywhere in a
+    /// request as `{{name}}`. Comment lines start with `#` or `//`, and a few
+    /// comments before a method line act as directives for that request:
+    ///
+    ///   # @name login
+    ///
+    ///   # @capture token = $.data.token
+    ///
+    ///   # @capture location = header Location
+    ///
+    ///   # @capture code = status
+    ///
+    ///   # @expect status 2xx
+    ///
+    ///   # @expect json .data.id == 42
+    ///
+    /// A capture stores a value from the response as a variable which can
+    /// be used by any later request in the file. An expectation is checked
+    /// like the matching --expect-* option, and header and body expectations
+    /// are also supported. Running stops at the first request which fails.
+    #[structopt(parse(from_os_str))]
+    pub file: PathBuf,
+
+    /// Set a variable, of the form `name=value`.
+    ///
+    /// Variables given here override those defined in the request file.
+    #[structopt(long = "var", number_of_values = 1, parse(try_from_str = parse_variable))]
+    pub variables: Vec<(String, String)>,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct CompletionsData {
+    /// The shell to print the script for.
+    #[structopt(possible_values = &["bash", "zsh", "fish"], required_unless = "list")]
+    pub shell: Option<String>,
+
+    /// Print the stored session or profile names, used by the scripts.
+    #[structopt(long, hidden = true, possible_values = &["sessions", "profiles"])]
+    pub list: Option<String>,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum ImportCommand {
+    /// Import a curl command, such as one from "Copy as cURL" in a browser,
+    /// use - to read stdin.
+    Curl {
+        command: String,
+        #[structopt(flatten)]
+        target: ImportTarget,
+    },
+    /// Import the requests recorded in a HAR file, use - to read stdin.
+    Har {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Only import the entry at this position, counting from 1.
+        #[structopt(long = "entry", number_of_values = 1)]
+        entries: Vec<usize>,
+        #[structopt(flatten)]
+        target: ImportTarget,
+    },
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct ImportTarget {
+    /// Send the requests instead of printing them.
+    #[structopt(long, conflicts_with = "output")]
+    pub run: bool,
+
+    /// The request file to write to instead of stdout.
+    #[structopt(short, long, parse(from_os_str))]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum HistoryCommand {
+    /// List the most recent requests, optionally only those matching a pattern.
+    List {
+        pattern: Option<String>,
+        /// The number of requests to list.
+        #[structopt(short = "n", long, default_value = "20")]
+        limit: usize,
+    },
+    /// Show everything recorded about a request.
+    Show { id: u64 },
+    /// Send a request again.
+    ///
+    /// Options such as --auth or --print apply as usual. The request uses
+    /// the session it was sent with unless --session names another.
+    Rerun { id: u64 },
+    /// Delete the history.
+    Clear,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum ConfigCommand {
+    /// Check the configuration file for errors and unknown keys.
+    Check,
+    /// Show the effective configuration and where each value came from.
+    ///
+    /// Command line options take precedence over environment variables,
+    /// which take precedence over the selected profile and then the rest of
+    /// the configuration file.
+    Show {
+        /// Show credentials unmasked.
+        #[structopt(long)]
+        show_secrets: bool,
+    },
+    /// Print the path of the configuration file.
+    Path,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum SessionCommand {
+    /// List stored sessions, optionally only those for one host.
+    List { host: Option<String> },
+    /// Show the headers, cookies and credentials stored in a session.
+    Show {
+        host: String,
+        name: String,
+        /// Show credentials, cookie values and sensitive headers unmasked.
+        #[structopt(long)]
+        show_secrets: bool,
+    },
+    /// Show the differences between two sessions for a host.
+    Diff {
+        host: String,
+        name: String,
+        other: String,
+        /// The host of the second session, if different from the first.
+        #[structopt(long)]
+        other_host: Option<String>,
+    },
+    /// Delete a session.
+    Rm { host: String, name: String },
+    /// Rename a session.
+    Rename {
+        host: String,
+        name: String,
+        new_name: String,
+    },
+    /// Write a session as JSON so that it can be imported on another machine.
+    ///
+    /// The session is written as stored: secrets encrypted with --key-file
+    /// or --encrypt-secrets stay encrypted and need the same key wherever
+    /// the session is imported.
+    Export {
+        host: String,
+        name: String,
+        /// The file to write to instead of stdout.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Import a session written by `session export`, use - to read stdin.
+    Import {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Store the session under this host instead of the exported one.
+        #[structopt(long)]
+        host: Option<String>,
+        /// Store the session under this name instead of the exported one.
+        #[structopt(long)]
+        name: Option<String>,
+        /// Overwrite an existing session with the same host and name.
+        #[structopt(long)]
+        force: bool,
+    },
+}
+
 #[derive(StructOpt, Debug)]
 #[structopt(rename_all = "screaming_snake_case")]
 pub enum Method {
@@ -210,6 +1185,53 @@ impl Method {
     }
 }
 
+/// The names of the variants of `Method`.
+const METHOD_SUBCOMMANDS: &[&str] = &["HEAD", "GET", "PUT", "POST", "PATCH", "DELETE"];
+
+/// Whether `arg` looks like a method, such as `OPTIONS`, which is not one of
+/// the built in method subcommands.
+fn is_custom_method(arg: &str) -> bool {
+    arg.starts_with(|c: char| c.is_ascii_uppercase())
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        && !METHOD_SUBCOMMANDS.contains(&arg)
+}
+
+/// The method given where a subcommand goes, at the first argument which is
+/// neither an option nor its value, if it is not a built in one.
+fn custom_method(args: &[OsString]) -> Option<String> {
+    let mut args = args.iter().skip(1).filter_map(|arg| arg.to_str());
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            return None;
+        }
+        if arg.starts_with('-') && arg.len() > 1 {
+            if !arg.contains('=') && takes_value(arg) {
+                args.next();
+            }
+            continue;
+        }
+        return Some(arg)
+            .filter(|arg| is_custom_method(arg))
+            .map(str::to_owned);
+    }
+    None
+}
+
+/// Whether the option `arg` is followed by its value, which clap tells by
+/// complaining when it is given alone.
+fn takes_value(arg: &str) -> bool {
+    match App::clap().get_matches_from_safe(["hurl", arg]) {
+        Err(e) => e.kind == structopt::clap::ErrorKind::EmptyValue,
+        Ok(_) => false,
+    }
+}
+
+pub fn parse_method(src: &str) -> HurlResult<reqwest::Method> {
+    reqwest::Method::from_bytes(src.as_bytes()).map_err(|_| Error::InvalidMethod(src.to_owned()))
+}
+
 impl From<&Method> for reqwest::Method {
     fn from(m: &Method) -> reqwest::Method {
         match m {
@@ -260,11 +1282,20 @@ pub struct MethodData {
     /// Raw JSON data from file -- key:=@filename
     ///
     ///   e.g. foo:=@bar.json becomes {"foo":{"bar":"this is from bar.json"}}
+    ///
+    /// The key of any data field may be a path which builds nested objects
+    /// and arrays, where `[]` appends to an array. Use `\.` or `\[` for a
+    /// literal dot or bracket in a key.
+    ///
+    ///   e.g. user[name]=x user[tags][]=a items[0][id]:=3 becomes
+    ///   {"items":[{"id":3}],"user":{"name":"x","tags":["a"]}}
+    ///
+    ///   user.name=x is the same as user[name]=x
     #[structopt(parse(try_from_str = parse_param))]
     pub parameters: Vec<Parameter>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Parameter {
     // :
     Header { key: String, value: String },
@@ -299,6 +1330,20 @@ impl Parameter {
     }
 }
 
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Parameter::Header { key, value } => write!(f, "{}:{}", key, value),
+            Parameter::Data { key, value } => write!(f, "{}={}", key, value),
+            Parameter::RawJsonData { key, value } => write!(f, "{}:={}", key, value),
+            Parameter::Query { key, value } => write!(f, "{}=={}", key, value),
+            Parameter::FormFile { key, filename } => write!(f, "{}@{}", key, filename),
+            Parameter::DataFile { key, filename } => write!(f, "{}=@{}", key, filename),
+            Parameter::RawJsonDataFile { key, filename } => write!(f, "{}:=@{}", key, filename),
+        }
+    }
+}
+
 #[derive(Debug)]
 enum Separator {
     Colon,
@@ -371,6 +1416,95 @@ fn gather_escapes<'a>(src: &'a str) -> Vec<Token<'a>> {
     }
 }
 
+fn pick<T>(profile: Option<T>, file: Option<T>) -> Option<(T, Source)> {
+    profile
+        .map(|v| (v, Source::Profile))
+        .or_else(|| file.map(|v| (v, Source::File)))
+}
+
+/// The parts of a request and response selected by --print.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Print {
+    pub request_headers: bool,
+    pub request_body: bool,
+    pub response_headers: bool,
+    pub response_body: bool,
+}
+
+pub const OUTPUT_FORMATS: &[&str] = &["text", "json"];
+
+/// How a response is printed, from --output-format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+fn parse_output_format(src: &str) -> HurlResult<OutputFormat> {
+    match src {
+        "text" => Ok(OutputFormat::Text),
+        "json" => Ok(OutputFormat::Json),
+        _ => Err(Error::InvalidOutputFormat(src.to_owned())),
+    }
+}
+
+pub const COLORS: &[&str] = &["always", "never", "auto"];
+
+/// When to colour the output, from --color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    Always,
+    Never,
+    Auto,
+}
+
+fn parse_color(src: &str) -> HurlResult<Color> {
+    match src {
+        "always" => Ok(Color::Always),
+        "never" => Ok(Color::Never),
+        "auto" => Ok(Color::Auto),
+        _ => Err(Error::InvalidColor(src.to_owned())),
+    }
+}
+
+pub fn parse_seconds(src: &str) -> HurlResult<Duration> {
+    match src.parse::<f64>() {
+        Ok(secs) if secs.is_finite() && secs > 0.0 => Ok(Duration::from_secs_f64(secs)),
+        _ => Err(Error::InvalidTimeout(src.to_owned())),
+    }
+}
+
+fn parse_count(src: &str) -> HurlResult<usize> {
+    match src.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(Error::InvalidCount(src.to_owned())),
+    }
+}
+
+fn parse_print(src: &str) -> HurlResult<Print> {
+    let mut print = Print::default();
+    for c in src.chars() {
+        match c {
+            'H' => print.request_headers = true,
+            'B' => print.request_body = true,
+            'h' => print.response_headers = true,
+            'b' => print.response_body = true,
+            _ => return Err(Error::InvalidPrint(src.to_owned())),
+        }
+    }
+    Ok(print)
+}
+
+fn parse_variable(src: &str) -> HurlResult<(String, String)> {
+    let mut parts = src.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) if !name.is_empty() => {
+            Ok((name.trim().to_owned(), value.to_owned()))
+        }
+        _ => Err(Error::ParameterMissingSeparator(src.to_owned())),
+    }
+}
+
 fn parse_param(src: &str) -> HurlResult<Parameter> {
     debug!("Parsing: {}", src);
     let separators = [":=@", "=@", "==", ":=", "@", "=", ":"];
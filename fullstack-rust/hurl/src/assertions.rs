@@ -0,0 +1,418 @@
+use crate::errors::{Error, HurlResult};
+use crate::jsonpath;
+use regex::Regex;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A check made against a response, e.g. from `--expect-status 2xx`.
+#[derive(Debug)]
+pub enum Assertion {
+    Status(Vec<StatusPattern>),
+    Header { name: String, value: Option<String> },
+    Json { path: String, op: Op, expected: Option<Value> },
+    Body(Regex),
+}
+
+/// Either an exact status code such as `201` or a class such as `2xx`.
+#[derive(Debug)]
+pub enum StatusPattern {
+    Exact(u16),
+    Class(u16),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    Exists,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn as_str(self) -> &'static str {
+        match self {
+            Op::Exists => "exists",
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Assertion::Status(patterns) => {
+                let patterns = patterns
+                    .iter()
+                    .map(|p| match p {
+                        StatusPattern::Exact(code) => code.to_string(),
+                        StatusPattern::Class(class) => format!("{}xx", class),
+                    })
+                    .collect::<Vec<String>>()
+                    .join(",");
+                write!(f, "status is {}", patterns)
+            }
+            Assertion::Header { name, value: None } => write!(f, "header {} is present", name),
+            Assertion::Header {
+                name,
+                value: Some(v),
+            } => write!(f, "header {} is {}", name, v),
+            Assertion::Json {
+                path,
+                op: Op::Exists,
+                ..
+            } => write!(f, "json {} exists", path),
+            Assertion::Json {
+                path,
+                op,
+                expected: Some(v),
+            } => write!(f, "json {} {} {}", path, op.as_str(), v),
+            Assertion::Json { path, op, .. } => write!(f, "json {} {}", path, op.as_str()),
+            Assertion::Body(re) => write!(f, "body matches /{}/", re),
+        }
+    }
+}
+
+/// Parse a status expectation such as `200`, `2xx` or `200,201,3xx`.
+pub fn parse_status(src: &str) -> HurlResult<Assertion> {
+    let mut patterns = Vec::new();
+    for part in src.split(',').map(str::trim) {
+        let lower = part.to_ascii_lowercase();
+        let pattern = if lower.len() == 3 && lower.ends_with("xx") {
+            lower[..1]
+                .parse::<u16>()
+                .ok()
+                .filter(|c| (1..=5).contains(c))
+                .map(StatusPattern::Class)
+        } else {
+            lower
+                .parse::<u16>()
+                .ok()
+                .filter(|c| (100..=599).contains(c))
+                .map(StatusPattern::Exact)
+        };
+        match pattern {
+            Some(p) => patterns.push(p),
+            None => return Err(Error::InvalidAssertion(src.to_owned())),
+        }
+    }
+    Ok(Assertion::Status(patterns))
+}
+
+/// Parse a header expectation, either `Name` or `Name:value`.
+pub fn parse_header(src: &str) -> HurlResult<Assertion> {
+    let mut parts = src.splitn(2, ':');
+    let name = parts.next().unwrap_or("").trim();
+    if name.is_empty() {
+        return Err(Error::InvalidAssertion(src.to_owned()));
+    }
+    Ok(Assertion::Header {
+        name: name.to_owned(),
+        value: parts.next().map(|v| v.trim().to_owned()),
+    })
+}
+
+/// Parse a JSON expectation of the form `path [op value]`, e.g. `.data.id == 42`.
+///
+/// The value is parsed as JSON if possible and otherwise used as a string.
+pub fn parse_json(src: &str) -> HurlResult<Assertion> {
+    let src = src.trim();
+    let (path, rest) = jsonpath::split_path(src);
+    jsonpath::parse(path)?;
+    let rest = rest.trim();
+    if rest.is_empty() {
+        return Ok(Assertion::Json {
+            path: path.to_owned(),
+            op: Op::Exists,
+            expected: None,
+        });
+    }
+
+    let ops = [
+        ("==", Op::Eq),
+        ("!=", Op::Ne),
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+    ];
+    let (op, value) = ops
+        .iter()
+        .find(|(s, _)| rest.starts_with(s))
+        .map(|(s, op)| (*op, rest[s.len()..].trim()))
+        .ok_or_else(|| Error::InvalidAssertion(src.to_owned()))?;
+    if value.is_empty() {
+        return Err(Error::InvalidAssertion(src.to_owned()));
+    }
+    let expected =
+        serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_owned()));
+    Ok(Assertion::Json {
+        path: path.to_owned(),
+        op,
+        expected: Some(expected),
+    })
+}
+
+/// Parse a regular expression which the response body must match.
+pub fn parse_body(src: &str) -> HurlResult<Assertion> {
+    Regex::new(src)
+        .map(Assertion::Body)
+        .map_err(|_| Error::InvalidAssertion(src.to_owned()))
+}
+
+/// The result of evaluating a single assertion.
+pub struct Outcome<'a> {
+    pub assertion: &'a Assertion,
+    pub failure: Option<String>,
+}
+
+impl Assertion {
+    pub fn evaluate(&self, resp: &reqwest::Response, body: &str) -> Option<String> {
+        match self {
+            Assertion::Status(patterns) => {
+                let code = resp.status().as_u16();
+                let matched = patterns.iter().any(|p| match p {
+                    StatusPattern::Exact(c) => *c == code,
+                    StatusPattern::Class(c) => code / 100 == *c,
+                });
+                if matched {
+                    None
+                } else {
+                    Some(format!("got {}", code))
+                }
+            }
+            Assertion::Header { name, value } => {
+                let actual = resp.headers().get_all(name.as_str());
+                let values: Vec<&str> = actual.iter().filter_map(|v| v.to_str().ok()).collect();
+                match value {
+                    _ if values.is_empty() => Some("header is missing".to_owned()),
+                    Some(v) if !values.iter().any(|a| a == v) => {
+                        Some(format!("got {}", values.join(", ")))
+                    }
+                    _ => None,
+                }
+            }
+            Assertion::Json { path, op, expected } => {
+                let json: Value = match serde_json::from_str(body) {
+                    Ok(json) => json,
+                    Err(_) => return Some("body is not JSON".to_owned()),
+                };
+                let actual = match jsonpath::select(&json, path) {
+                    Ok(Some(actual)) => actual,
+                    Ok(None) => return Some("path does not exist".to_owned()),
+                    Err(e) => return Some(e.to_string()),
+                };
+                let expected = match expected {
+                    Some(expected) => expected,
+                    None => return None,
+                };
+                let ordering = compare(&actual, expected);
+                let passed = match op {
+                    Op::Exists => true,
+                    Op::Eq => ordering == Some(Ordering::Equal),
+                    Op::Ne => ordering != Some(Ordering::Equal),
+                    Op::Lt => ordering == Some(Ordering::Less),
+                    Op::Le => ordering.map(|o| o != Ordering::Greater).unwrap_or(false),
+                    Op::Gt => ordering == Some(Ordering::Greater),
+                    Op::Ge => ordering.map(|o| o != Ordering::Less).unwrap_or(false),
+                };
+                if passed {
+                    None
+                } else {
+                    Some(format!("got {}", actual))
+                }
+            }
+            Assertion::Body(re) => {
+                if re.is_match(body) {
+                    None
+                } else {
+                    Some("body does not match".to_owned())
+                }
+            }
+        }
+    }
+}
+
+fn compare(actual: &Value, expected: &Value) -> Option<Ordering> {
+    match (actual, expected) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (a, b) if a == b => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+/// Evaluate every assertion, printing a summary to stderr.
+///
+/// Returns an error carrying the number of failed checks if any failed.
+pub fn check(assertions: &[&Assertion], resp: &reqwest::Response, body: &str) -> HurlResult<()> {
+    if assertions.is_empty() {
+        return Ok(());
+    }
+    let outcomes: Vec<Outcome> = assertions
+        .iter()
+        .map(|assertion| Outcome {
+            assertion,
+            failure: assertion.evaluate(resp, body),
+        })
+        .collect();
+
+    let failed = outcomes.iter().filter(|o| o.failure.is_some()).count();
+    for outcome in outcomes.iter() {
+        match &outcome.failure {
+            Some(reason) => eprintln!("FAIL {}: {}", outcome.assertion, reason),
+            None => eprintln!("PASS {}", outcome.assertion),
+        }
+    }
+    eprintln!("{} passed, {} failed", outcomes.len() - failed, failed);
+
+    if failed > 0 {
+        Err(Error::AssertionsFailed(failed))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, headers: &[(&str, &str)]) -> reqwest::Response {
+        let mut builder = http::Response::builder();
+        builder.status(status);
+        for (key, value) in headers {
+            builder.header(*key, *value);
+        }
+        builder.body("").unwrap().into()
+    }
+
+    fn is_invalid(result: HurlResult<Assertion>) -> bool {
+        matches!(result, Err(Error::InvalidAssertion(_)))
+    }
+
+    #[test]
+    fn parses_status_codes_and_classes() {
+        let assertion = parse_status("200, 2XX,301").unwrap();
+        assert_eq!(assertion.to_string(), "status is 200,2xx,301");
+        assert_eq!(assertion.evaluate(&response(204, &[]), ""), None);
+        assert_eq!(assertion.evaluate(&response(301, &[]), ""), None);
+        assert_eq!(
+            assertion.evaluate(&response(404, &[]), ""),
+            Some("got 404".to_owned())
+        );
+
+        assert!(is_invalid(parse_status("")));
+        assert!(is_invalid(parse_status("6xx")));
+        assert!(is_invalid(parse_status("99")));
+        assert!(is_invalid(parse_status("2x")));
+        assert!(is_invalid(parse_status("200,ok")));
+    }
+
+    #[test]
+    fn parses_headers() {
+        let present = parse_header("ETag").unwrap();
+        assert_eq!(present.to_string(), "header ETag is present");
+        let exact = parse_header("content-type: text/plain").unwrap();
+        assert_eq!(exact.to_string(), "header content-type is text/plain");
+        assert!(is_invalid(parse_header(": value")));
+
+        let resp = response(200, &[("Content-Type", "text/plain"), ("ETag", "\"1\"")]);
+        assert_eq!(present.evaluate(&resp, ""), None);
+        assert_eq!(exact.evaluate(&resp, ""), None);
+        let resp = response(200, &[("Content-Type", "text/html")]);
+        assert_eq!(
+            present.evaluate(&resp, ""),
+            Some("header is missing".to_owned())
+        );
+        assert_eq!(exact.evaluate(&resp, ""), Some("got text/html".to_owned()));
+    }
+
+    #[test]
+    fn parses_json_comparisons() {
+        assert_eq!(parse_json(".id").unwrap().to_string(), "json .id exists");
+        assert_eq!(
+            parse_json(".id >= 42").unwrap().to_string(),
+            "json .id >= 42"
+        );
+        assert_eq!(
+            parse_json("$['a b'] == ok").unwrap().to_string(),
+            "json $['a b'] == \"ok\""
+        );
+        assert!(is_invalid(parse_json(".id ~ 1")));
+        assert!(is_invalid(parse_json(".id ==")));
+        assert!(matches!(
+            parse_json(".items["),
+            Err(Error::InvalidJsonPath(_))
+        ));
+    }
+
+    #[test]
+    fn evaluates_json_comparisons() {
+        let body = r#"{"id": 42, "name": "fig", "tags": ["a"], "none": null}"#;
+        let resp = response(200, &[]);
+        let check = |src: &str| parse_json(src).unwrap().evaluate(&resp, body);
+
+        for passing in &[
+            ".id",
+            ".none",
+            ".id == 42",
+            ".id == 42.0",
+            ".id != 41",
+            ".id < 43",
+            ".id <= 42",
+            ".id > 41.5",
+            ".id >= 42",
+            ".name == fig",
+            ".name == \"fig\"",
+            ".name < grape",
+            ".tags == [\"a\"]",
+            ".none == null",
+        ] {
+            assert_eq!(check(passing), None, "{}", passing);
+        }
+        assert_eq!(check(".id > 42"), Some("got 42".to_owned()));
+        assert_eq!(check(".name == 42"), Some("got \"fig\"".to_owned()));
+        assert_eq!(check(".name > 1"), Some("got \"fig\"".to_owned()));
+        assert_eq!(check(".tags != [\"a\"]"), Some("got [\"a\"]".to_owned()));
+        assert_eq!(check(".missing"), Some("path does not exist".to_owned()));
+        assert_eq!(
+            parse_json(".id").unwrap().evaluate(&resp, "<html>"),
+            Some("body is not JSON".to_owned())
+        );
+    }
+
+    #[test]
+    fn evaluates_body_patterns() {
+        let assertion = parse_body("^hello, w.rld$").unwrap();
+        assert_eq!(
+            assertion.evaluate(&response(200, &[]), "hello, world"),
+            None
+        );
+        assert_eq!(
+            assertion.evaluate(&response(200, &[]), "goodbye"),
+            Some("body does not match".to_owned())
+        );
+        assert!(is_invalid(parse_body("(unclosed")));
+    }
+
+    #[test]
+    fn checks_every_assertion() {
+        let status = parse_status("2xx").unwrap();
+        let header = parse_header("Location").unwrap();
+        let resp = response(201, &[]);
+        assert!(check(&[], &resp, "").is_ok());
+        assert!(check(&[&status], &resp, "").is_ok());
+        assert!(matches!(
+            check(&[&status, &header], &resp, ""),
+            Err(Error::AssertionsFailed(1))
+        ));
+    }
+}
@@ -0,0 +1,606 @@
+use crate::app::App;
+use crate::client::parse_auth;
+use crate::cookies;
+use crate::errors::{Error, HurlResult};
+use crate::history::DateTime;
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::{Method, Request, RequestBuilder, Response, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The schemes accepted by --auth-type.
+pub const AUTH_TYPES: &[&str] = &["basic", "bearer", "digest", "apikey", "sigv4"];
+
+/// The header an API key is sent in when --auth names none.
+const DEFAULT_API_KEY_HEADER: &str = "X-API-Key";
+
+/// How the credentials given by --auth and --token are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthType {
+    Basic,
+    Bearer,
+    Digest,
+    ApiKey,
+    SigV4,
+}
+
+impl fmt::Display for AuthType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AuthType::Basic => "basic",
+            AuthType::Bearer => "bearer",
+            AuthType::Digest => "digest",
+            AuthType::ApiKey => "apikey",
+            AuthType::SigV4 => "sigv4",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub fn parse_auth_type(src: &str) -> HurlResult<AuthType> {
+    match src {
+        "basic" => Ok(AuthType::Basic),
+        "bearer" => Ok(AuthType::Bearer),
+        "digest" => Ok(AuthType::Digest),
+        "apikey" => Ok(AuthType::ApiKey),
+        "sigv4" => Ok(AuthType::SigV4),
+        _ => Err(Error::InvalidAuthType(src.to_owned())),
+    }
+}
+
+/// Whether a 401 response is answered with Digest credentials.
+pub fn is_digest(app: &App) -> bool {
+    app.auth_type == Some(AuthType::Digest)
+}
+
+/// Add an API key given as `header:key`, or as a key alone which is sent as
+/// X-API-Key.
+pub fn api_key(builder: RequestBuilder, auth: &str) -> HurlResult<RequestBuilder> {
+    let (name, key) = match auth.find(':') {
+        Some(idx) => (&auth[..idx], &auth[idx + 1..]),
+        None => (DEFAULT_API_KEY_HEADER, auth),
+    };
+    let name = HeaderName::from_bytes(name.as_bytes())
+        .map_err(|_| Error::InvalidHeader(name.to_owned()))?;
+    let value = HeaderValue::from_str(key).map_err(|_| Error::InvalidHeader(name.to_string()))?;
+    Ok(builder.header(name, value))
+}
+
+/// The Authorization header answering the Digest challenge of a 401
+/// response, or None when the server did not ask for Digest.
+pub fn digest(
+    auth: Option<&str>,
+    resp: &Response,
+    request: &Request,
+    body: Option<&[u8]>,
+) -> HurlResult<Option<String>> {
+    let challenge = resp
+        .headers()
+        .get_all(WWW_AUTHENTICATE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .find(|v| v.len() > 7 && v[..7].eq_ignore_ascii_case("digest "));
+    let challenge = match challenge {
+        Some(challenge) => parse_challenge(&challenge[7..]),
+        None => return Ok(None),
+    };
+    let auth = auth.ok_or(Error::MissingCredentials(AuthType::Digest))?;
+    let (username, password) = parse_auth(auth)?;
+    let password = password.unwrap_or_default();
+
+    let url = request.url();
+    let uri = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    };
+    let mut cnonce = [0u8; 8];
+    OsRng.fill_bytes(&mut cnonce);
+    let cnonce: String = cnonce.iter().map(|b| format!("{:02x}", b)).collect();
+    let header = digest_header(
+        &challenge,
+        &username,
+        &password,
+        request.method(),
+        &uri,
+        body,
+        &cnonce,
+    )?;
+    Ok(Some(header))
+}
+
+/// The Authorization header answering a parsed Digest challenge for a
+/// request to `uri`, with `cnonce` as the client nonce.
+fn digest_header(
+    challenge: &BTreeMap<String, String>,
+    username: &str,
+    password: &str,
+    method: &Method,
+    uri: &str,
+    body: Option<&[u8]>,
+    cnonce: &str,
+) -> HurlResult<String> {
+    let realm = challenge.get("realm").map(String::as_str).unwrap_or("");
+    let nonce = challenge.get("nonce").map(String::as_str).unwrap_or("");
+    let algorithm = challenge
+        .get("algorithm")
+        .map(String::as_str)
+        .unwrap_or("MD5");
+    let hash_bytes: fn(&[u8]) -> String = match algorithm.to_uppercase().trim_end_matches("-SESS") {
+        "MD5" => |b| format!("{:x}", Md5::digest(b)),
+        "SHA-256" => |b| format!("{:x}", Sha256::digest(b)),
+        _ => return Err(Error::UnsupportedDigest(algorithm.to_owned())),
+    };
+    let hash = |s: &str| hash_bytes(s.as_bytes());
+    // Prefer auth over auth-int, which needs the whole body up front.
+    let offered: Vec<&str> = challenge
+        .get("qop")
+        .map(|q| q.split(',').map(str::trim).collect())
+        .unwrap_or_default();
+    let qop = if offered.contains(&"auth") {
+        Some("auth")
+    } else if offered.contains(&"auth-int") && body.is_some() {
+        Some("auth-int")
+    } else if offered.is_empty() {
+        None
+    } else {
+        return Err(Error::UnsupportedDigest(offered.join(", ")));
+    };
+
+    let nc = "00000001";
+
+    let mut ha1 = hash(&format!("{}:{}:{}", username, realm, password));
+    if algorithm.to_uppercase().ends_with("-SESS") {
+        ha1 = hash(&format!("{}:{}:{}", ha1, nonce, cnonce));
+    }
+    let ha2 = match qop {
+        Some("auth-int") => {
+            let body_hash = hash_bytes(body.unwrap_or_default());
+            hash(&format!("{}:{}:{}", method, uri, body_hash))
+        }
+        _ => hash(&format!("{}:{}", method, uri)),
+    };
+    let response = match qop {
+        Some(qop) => hash(&format!(
+            "{}:{}:{}:{}:{}:{}",
+            ha1, nonce, nc, cnonce, qop, ha2
+        )),
+        None => hash(&format!("{}:{}:{}", ha1, nonce, ha2)),
+    };
+
+    let mut header = format!(
+        "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}, response=\"{}\"",
+        username, realm, nonce, uri, algorithm, response
+    );
+    if let Some(qop) = qop {
+        header.push_str(&format!(", qop={}, nc={}, cnonce=\"{}\"", qop, nc, cnonce));
+    }
+    if let Some(opaque) = challenge.get("opaque") {
+        header.push_str(&format!(", opaque=\"{}\"", opaque));
+    }
+    Ok(header)
+}
+
+/// Split the parameters of a challenge, which may be quoted strings
+/// holding commas.
+fn parse_challenge(s: &str) -> BTreeMap<String, String> {
+    let mut params = BTreeMap::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        while let Some(c) = chars.peek() {
+            if *c == ',' || c.is_whitespace() {
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        if key.is_empty() {
+            break;
+        }
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.peek() {
+                if *c == ',' {
+                    break;
+                }
+                value.push(*c);
+                chars.next();
+            }
+        }
+        params.insert(key.trim().to_lowercase(), value.trim().to_owned());
+    }
+    params
+}
+
+/// Sign the request with AWS Signature Version 4, using `auth` as the
+/// access key id and secret key and --token as the session token. The
+/// region and service come from --aws-region and --aws-service, or else
+/// from an amazonaws.com host name.
+///
+/// A multipart body is streamed, so its hash is left out of the signature,
+/// which only S3 accepts.
+pub fn sign_v4(
+    app: &App,
+    auth: &Option<String>,
+    request: &mut Request,
+    body: Option<&[u8]>,
+    streamed: bool,
+) -> HurlResult<()> {
+    let auth = auth
+        .as_ref()
+        .ok_or(Error::MissingCredentials(AuthType::SigV4))?;
+    let (access_key, secret_key) = match parse_auth(auth)? {
+        (access_key, Some(secret_key)) => (access_key, secret_key),
+        (_, None) => return Err(Error::MissingCredentials(AuthType::SigV4)),
+    };
+    let (region, service) = aws_scope(app, request.url())?;
+    if streamed && service != "s3" {
+        return Err(Error::UnsignedPayload(service));
+    }
+
+    let t = DateTime::from_secs(cookies::now());
+    let date = format!("{:04}{:02}{:02}", t.year, t.month, t.day);
+    let timestamp = format!("{}T{:02}{:02}{:02}Z", date, t.hour, t.minute, t.second);
+    let payload_hash = if streamed {
+        "UNSIGNED-PAYLOAD".to_owned()
+    } else {
+        format!("{:x}", Sha256::digest(body.unwrap_or_default()))
+    };
+
+    let headers = request.headers_mut();
+    headers.insert("x-amz-date", header_value("x-amz-date", &timestamp)?);
+    headers.insert(
+        "x-amz-content-sha256",
+        header_value("x-amz-content-sha256", &payload_hash)?,
+    );
+    if let Some(token) = &app.token {
+        headers.insert(
+            "x-amz-security-token",
+            header_value("x-amz-security-token", token)?,
+        );
+    }
+
+    let (canonical_request, signed_headers) = canonical_request(request, &service, &payload_hash);
+    let (scope, signature) = signature(
+        &secret_key,
+        &timestamp,
+        &region,
+        &service,
+        &canonical_request,
+    );
+
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key, scope, signed_headers, signature
+    );
+    request.headers_mut().insert(
+        AUTHORIZATION,
+        header_value("authorization", &authorization)?,
+    );
+    Ok(())
+}
+
+/// The canonical request for `request`, which already has its x-amz-*
+/// headers, and the names of the headers it signs.
+fn canonical_request(request: &Request, service: &str, payload_hash: &str) -> (String, String) {
+    let url = request.url();
+    let mut canonical_headers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    if let Some(host) = url.host_str() {
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_owned(),
+        };
+        canonical_headers.insert("host".to_owned(), vec![host]);
+    }
+    for (key, value) in request.headers().iter() {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        canonical_headers
+            .entry(key.as_str().to_owned())
+            .or_default()
+            .push(value.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+    let signed_headers = canonical_headers
+        .keys()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(";");
+    let mut canonical_request = format!(
+        "{}\n{}\n{}\n",
+        request.method(),
+        canonical_path(url, service),
+        canonical_query(url)
+    );
+    for (key, values) in canonical_headers.iter() {
+        canonical_request.push_str(&format!("{}:{}\n", key, values.join(",")));
+    }
+    canonical_request.push_str(&format!("\n{}\n{}", signed_headers, payload_hash));
+    (canonical_request, signed_headers)
+}
+
+/// The credential scope and the signature of a canonical request made at
+/// `timestamp`, as in 20150830T123600Z.
+fn signature(
+    secret_key: &str,
+    timestamp: &str,
+    region: &str,
+    service: &str,
+    canonical_request: &str,
+) -> (String, String) {
+    let date = &timestamp[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+        timestamp,
+        scope,
+        Sha256::digest(canonical_request.as_bytes())
+    );
+    let mut key = hmac(format!("AWS4{}", secret_key).as_bytes(), date);
+    for part in [region, service, "aws4_request"].iter() {
+        key = hmac(&key, part);
+    }
+    let signature = hmac(&key, &string_to_sign)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    (scope, signature)
+}
+
+/// The region and service to sign for.
+fn aws_scope(app: &App, url: &Url) -> HurlResult<(String, String)> {
+    // Hosts look like service.region.amazonaws.com, or
+    // bucket.s3.region.amazonaws.com.
+    let host = url.host_str().unwrap_or("");
+    let labels: Vec<&str> = host.split('.').collect();
+    let (host_service, host_region) = match labels.iter().rposition(|l| *l == "amazonaws") {
+        Some(idx) if idx >= 2 => (Some(labels[idx - 2]), Some(labels[idx - 1])),
+        Some(1) => (Some(labels[0]), Some("us-east-1")),
+        _ => (None, None),
+    };
+    let region = app
+        .aws_region
+        .clone()
+        .or_else(|| host_region.map(str::to_owned));
+    let service = app
+        .aws_service
+        .clone()
+        .or_else(|| host_service.map(str::to_owned));
+    match (region, service) {
+        (Some(region), Some(service)) => Ok((region, service)),
+        _ => Err(Error::MissingAwsScope(host.to_owned())),
+    }
+}
+
+/// The path with each segment encoded. The path is already encoded once,
+/// and every service but S3 expects it to be encoded again.
+fn canonical_path(url: &Url, service: &str) -> String {
+    let path = url.path();
+    if service == "s3" {
+        return path.to_owned();
+    }
+    path.split('/')
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The query with each name and value encoded the AWS way and sorted. The
+/// pairs are decoded from the query as it is sent, where `+` is not a space.
+fn canonical_query(url: &Url) -> String {
+    let query = url.query().unwrap_or("");
+    let mut pairs: Vec<(String, String)> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = percent_decode(parts.next().unwrap_or(""));
+            let value = percent_decode(parts.next().unwrap_or(""));
+            (uri_encode(&key), uri_encode(&value))
+        })
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 3 <= bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Percent-encode everything but the unreserved characters.
+fn uri_encode<S: AsRef<[u8]> + ?Sized>(s: &S) -> String {
+    let mut encoded = String::new();
+    for &b in s.as_ref() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes a key of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn header_value(name: &str, value: &str) -> HurlResult<HeaderValue> {
+    HeaderValue::from_str(value).map_err(|_| Error::InvalidHeader(name.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The example of RFC 7616, section 3.9.1.
+    const CHALLENGE: &str = r#"realm="http-auth@example.org", qop="auth, auth-int", algorithm=SHA-256, nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v", opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#;
+    const CNONCE: &str = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+
+    fn rfc_7616_header(algorithm: &str) -> HurlResult<String> {
+        let mut challenge = parse_challenge(CHALLENGE);
+        challenge.insert("algorithm".to_owned(), algorithm.to_owned());
+        digest_header(
+            &challenge,
+            "Mufasa",
+            "Circle of Life",
+            &Method::GET,
+            "/dir/index.html",
+            None,
+            CNONCE,
+        )
+    }
+
+    #[test]
+    fn parses_challenges() {
+        let challenge = parse_challenge(CHALLENGE);
+        assert_eq!(challenge["realm"], "http-auth@example.org");
+        assert_eq!(challenge["qop"], "auth, auth-int");
+        assert_eq!(challenge["algorithm"], "SHA-256");
+        assert_eq!(
+            challenge["opaque"],
+            "FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS"
+        );
+
+        let challenge = parse_challenge(r#"Realm="a \"b\", c" ,stale=FALSE"#);
+        assert_eq!(challenge["realm"], r#"a "b", c"#);
+        assert_eq!(challenge["stale"], "FALSE");
+        assert!(parse_challenge("").is_empty());
+    }
+
+    #[test]
+    fn answers_the_rfc_7616_examples() {
+        let header = rfc_7616_header("MD5").unwrap();
+        assert!(header.contains(r#"response="8ca523f5e9506fed4657c9700eebdbec""#));
+        assert!(header.contains(r#"cnonce="f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ""#));
+        assert!(header.contains("qop=auth, nc=00000001"));
+        assert!(header.contains(r#"opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#));
+
+        let header = rfc_7616_header("SHA-256").unwrap();
+        assert!(header.contains(
+            r#"response="753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1""#
+        ));
+        assert!(header.contains("algorithm=SHA-256"));
+    }
+
+    #[test]
+    fn answers_the_rfc_2617_example() {
+        let challenge = parse_challenge(
+            r#"realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#,
+        );
+        let header = digest_header(
+            &challenge,
+            "Mufasa",
+            "Circle Of Life",
+            &Method::GET,
+            "/dir/index.html",
+            None,
+            "0a4f113b",
+        )
+        .unwrap();
+        assert!(header.contains(r#"response="6629fae49393a05397450978507c4ef1""#));
+    }
+
+    #[test]
+    fn rejects_unknown_digest_algorithms() {
+        assert!(rfc_7616_header("SHA-512-256").is_err());
+        let mut challenge = parse_challenge(CHALLENGE);
+        challenge.insert("qop".to_owned(), "auth-int".to_owned());
+        let result = digest_header(&challenge, "u", "p", &Method::GET, "/", None, CNONCE);
+        assert!(result.is_err());
+    }
+
+    // The get-vanilla cases of the AWS Signature Version 4 test suite.
+    const SECRET_KEY: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+    const TIMESTAMP: &str = "20150830T123600Z";
+    const EMPTY_HASH: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    fn vanilla_request(url: &str) -> Request {
+        let mut request = Request::new(Method::GET, Url::parse(url).unwrap());
+        request
+            .headers_mut()
+            .insert("x-amz-date", HeaderValue::from_static(TIMESTAMP));
+        request
+    }
+
+    #[test]
+    fn signs_the_aws_test_suite_requests() {
+        let request = vanilla_request("https://example.amazonaws.com/");
+        let (canonical, signed_headers) = canonical_request(&request, "service", EMPTY_HASH);
+        assert_eq!(
+            canonical,
+            format!(
+                "GET\n/\n\nhost:example.amazonaws.com\nx-amz-date:{}\n\nhost;x-amz-date\n{}",
+                TIMESTAMP, EMPTY_HASH
+            )
+        );
+        assert_eq!(signed_headers, "host;x-amz-date");
+        let (scope, signed) = signature(SECRET_KEY, TIMESTAMP, "us-east-1", "service", &canonical);
+        assert_eq!(scope, "20150830/us-east-1/service/aws4_request");
+        assert_eq!(
+            signed,
+            "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+
+        let request = vanilla_request("https://example.amazonaws.com/?Param2=value2&Param1=value1");
+        let (canonical, _) = canonical_request(&request, "service", EMPTY_HASH);
+        assert!(canonical.starts_with("GET\n/\nParam1=value1&Param2=value2\n"));
+        let (_, signed) = signature(SECRET_KEY, TIMESTAMP, "us-east-1", "service", &canonical);
+        assert_eq!(
+            signed,
+            "b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"
+        );
+    }
+
+    #[test]
+    fn canonicalizes_queries_as_sent() {
+        let query = |url: &str| canonical_query(&Url::parse(url).unwrap());
+        assert_eq!(query("http://x/?b=2&a=1&a=0"), "a=0&a=1&b=2");
+        assert_eq!(query("http://x/?q=a+b&r=a%20b"), "q=a%2Bb&r=a%20b");
+        assert_eq!(query("http://x/?k=%7e%2f&flag"), "flag=&k=~%2F");
+        assert_eq!(query("http://x/?x=%zz&y=caf%C3%A9"), "x=%25zz&y=caf%C3%A9");
+        assert_eq!(query("http://x/"), "");
+    }
+
+    #[test]
+    fn encodes_paths_again_outside_s3() {
+        let url = Url::parse("http://x/a b/c%2Fd").unwrap();
+        assert_eq!(canonical_path(&url, "s3"), "/a%20b/c%2Fd");
+        assert_eq!(canonical_path(&url, "service"), "/a%2520b/c%252Fd");
+    }
+}
@@ -0,0 +1,127 @@
+use crate::app::App;
+use crate::client::Prepared;
+use crate::errors::{Error, HurlResult};
+use crate::timing::millis;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The outcome of one of the repeated requests.
+struct Sample {
+    latency: Duration,
+    result: Result<(u16, u64), String>,
+}
+
+/// Send the request `count` times over a number of threads given by
+/// --concurrency, then print the latency percentiles, the statuses and the
+/// throughput.
+pub fn run(app: &App, prepared: Prepared, count: usize) -> HurlResult<()> {
+    let concurrency = app.concurrency.unwrap_or(1).min(count);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = mpsc::channel();
+    let start = Instant::now();
+
+    let mut workers = Vec::with_capacity(concurrency);
+    for _ in 0..concurrency {
+        let (client, template) = prepared.repeatable().ok_or(Error::RepeatStreamingBody)?;
+        let next = Arc::clone(&next);
+        let tx = tx.clone();
+        workers.push(thread::spawn(move || {
+            while next.fetch_add(1, Ordering::SeqCst) < count {
+                let request = match template.try_clone() {
+                    Some(request) => request,
+                    None => break,
+                };
+                let sent = Instant::now();
+                let result = client
+                    .execute(request)
+                    .and_then(|mut resp| {
+                        let size = resp.copy_to(&mut io::sink())?;
+                        Ok((resp.status().as_u16(), size))
+                    })
+                    .map_err(|e| e.to_string());
+                let sample = Sample {
+                    latency: sent.elapsed(),
+                    result,
+                };
+                if tx.send(sample).is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+    drop(tx);
+    let samples: Vec<Sample> = rx.iter().collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    report(&samples, concurrency, start.elapsed());
+    Ok(())
+}
+
+fn report(samples: &[Sample], concurrency: usize, elapsed: Duration) {
+    let secs = elapsed.as_secs_f64();
+    println!(
+        "Sent {} requests in {:.2} s with concurrency {} ({:.1} requests/s)",
+        samples.len(),
+        secs,
+        concurrency,
+        samples.len() as f64 / secs
+    );
+
+    let mut latencies: Vec<Duration> = samples
+        .iter()
+        .filter(|s| s.result.is_ok())
+        .map(|s| s.latency)
+        .collect();
+    latencies.sort();
+    if !latencies.is_empty() {
+        let mean = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+        println!("\nLatency");
+        println!("  min   {:>12}", millis(latencies[0]));
+        println!("  mean  {:>12}", millis(mean));
+        for p in [50, 90, 95, 99].iter() {
+            println!("  p{:<5}{:>12}", p, millis(percentile(&latencies, *p)));
+        }
+        println!("  max   {:>12}", millis(latencies[latencies.len() - 1]));
+    }
+
+    let mut statuses: BTreeMap<u16, usize> = BTreeMap::new();
+    let mut errors: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut bytes = 0;
+    for sample in samples.iter() {
+        match &sample.result {
+            Ok((status, size)) => {
+                *statuses.entry(*status).or_insert(0) += 1;
+                bytes += size;
+            }
+            Err(e) => *errors.entry(e.as_str()).or_insert(0) += 1,
+        }
+    }
+    if !statuses.is_empty() {
+        println!("\nStatus");
+        for (status, n) in statuses.iter() {
+            println!("  {}  {}", status, n);
+        }
+    }
+    if !errors.is_empty() {
+        println!("\nErrors");
+        for (error, n) in errors.iter() {
+            println!("  {}  {}", n, error);
+        }
+    }
+    println!(
+        "\nReceived {} bytes ({:.1} kB/s)",
+        bytes,
+        bytes as f64 / 1000.0 / secs
+    );
+}
+
+/// The nearest-rank percentile of sorted latencies.
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    let rank = (p * sorted.len()).div_ceil(100);
+    sorted[rank.max(1) - 1]
+}
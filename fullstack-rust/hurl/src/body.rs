@@ -0,0 +1,105 @@
+use crate::app::App;
+use crate::errors::{Error, HurlResult};
+use log::trace;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// A request body along with the content type to send it with if the
+/// request does not set one.
+#[derive(Debug)]
+pub struct RawBody {
+    pub bytes: Vec<u8>,
+    pub content_type: Option<&'static str>,
+}
+
+impl RawBody {
+    /// A body whose content type is guessed from its contents.
+    pub fn new(bytes: Vec<u8>) -> Self {
+        let content_type = sniff(&bytes);
+        RawBody {
+            bytes,
+            content_type,
+        }
+    }
+
+    /// A body read from a file, whose content type is guessed from the file
+    /// extension and otherwise from its contents.
+    pub fn from_file(path: &Path) -> HurlResult<Self> {
+        trace!("Reading body from {}", path.display());
+        let bytes = std::fs::read(path).map_err(|e| Error::File(path.to_owned(), e))?;
+        let content_type = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(by_extension)
+            .or_else(|| sniff(&bytes));
+        Ok(RawBody {
+            bytes,
+            content_type,
+        })
+    }
+}
+
+/// The body given by --raw, or piped to stdin, if there is one.
+///
+/// Stdin is only read when it is not a terminal and --ignore-stdin is not
+/// given, and an empty stdin means there is no body.
+pub fn from_app(app: &App) -> HurlResult<Option<RawBody>> {
+    if let Some(raw) = &app.raw {
+        return match raw.strip_prefix('@') {
+            Some(path) => RawBody::from_file(Path::new(path)).map(Some),
+            None => Ok(Some(RawBody::new(raw.clone().into_bytes()))),
+        };
+    }
+    if app.ignore_stdin || atty::is(atty::Stream::Stdin) {
+        return Ok(None);
+    }
+    let mut bytes = Vec::new();
+    io::stdin().read_to_end(&mut bytes)?;
+    trace!("Read {} bytes from stdin", bytes.len());
+    if bytes.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(RawBody::new(bytes)))
+    }
+}
+
+fn by_extension(extension: &str) -> Option<&'static str> {
+    let content_type = match extension.to_ascii_lowercase().as_str() {
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "html" | "htm" => "text/html",
+        "txt" => "text/plain",
+        "csv" => "text/csv",
+        "yaml" | "yml" => "application/yaml",
+        "js" => "application/javascript",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        _ => return None,
+    };
+    Some(content_type)
+}
+
+fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.is_empty() {
+        return None;
+    }
+    if serde_json::from_slice::<serde_json::Value>(bytes).is_ok() {
+        return Some("application/json");
+    }
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) if !text.contains('\0') => text.trim_start(),
+        _ => return Some("application/octet-stream"),
+    };
+    let lower = text.get(..64).unwrap_or(text).to_ascii_lowercase();
+    if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        Some("text/html")
+    } else if lower.starts_with('<') {
+        Some("application/xml")
+    } else {
+        Some("text/plain")
+    }
+}
@@ -1,41 +1,143 @@
 use crate::app::{App, Method, Parameter};
+use crate::auth::{self, AuthType};
 use std::time::Instant;
+use crate::body::{self, RawBody};
+use crate::credentials;
+use crate::datapath;
+use crate::download;
+use crate::history;
+use crate::oauth;
 use crate::errors::{Error, HurlResult};
 use crate::session::Session;
+use crate::tls;
 use log::{info, debug, trace, log_enabled, self};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, RANGE};
 use reqwest::multipart::Form;
-use reqwest::{Client, RequestBuilder, Response, Url};
-use serde_json::Value;
-use std::collections::HashMap;
+use reqwest::{Client, Proxy, RedirectPolicy, Request, RequestBuilder, Response, Url};
+use serde::Serialize;
+use serde_json::{Map, Value};
 use std::fs::File;
 use std::io::BufReader;
+use std::sync::{Arc, Mutex};
 
-pub fn perform_method(
+/// The most redirects followed when --max-redirects is not given.
+pub const DEFAULT_MAX_REDIRECTS: usize = 30;
+
+/// The redirects followed while sending a request, oldest first.
+pub type Redirects = Arc<Mutex<Vec<Redirect>>>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Redirect {
+    pub status: u16,
+    pub from: String,
+    pub to: String,
+}
+
+/// A request which is ready to be sent, along with a copy of its body.
+pub struct Prepared {
+    client: Client,
+    pub request: Request,
+    pub body: Option<Vec<u8>>,
+    pub parts: Vec<Part>,
+    pub entry: history::Entry,
+    /// The credentials which were looked up for --auth.
+    pub auth: Option<String>,
+    pub redirects: Redirects,
+}
+
+/// A field of a multipart body, which is kept so that the request can be
+/// exported.
+#[derive(Debug, Clone)]
+pub enum Part {
+    Text(String, String),
+    File(String, String),
+}
+
+impl Prepared {
+    pub fn send(self) -> HurlResult<Response> {
+        if log_enabled!(log::Level::Info) {
+            let start = Instant::now();
+            let result = self.client.execute(self.request).map_err(From::from);
+            let elapsed = start.elapsed();
+            info!("Elapsed time: {:?}", elapsed);
+            result
+        } else {
+            self.client.execute(self.request).map_err(From::from)
+        }
+    }
+
+    /// The client and a copy of the request, for sending it more than once.
+    /// A multipart body cannot be copied.
+    pub fn repeatable(&self) -> Option<(Client, Request)> {
+        let request = self.request.try_clone()?;
+        Some((self.client.clone(), request))
+    }
+}
+
+pub fn prepare_method(
     app: &App,
     method: &Method,
     session: &mut Option<Session>,
-) -> HurlResult<Response> {
+) -> HurlResult<Prepared> {
     let method_data = method.data();
-    perform(
+    prepare(
         app,
-        method.into(),
+        app.method.clone().unwrap_or_else(|| method.into()),
         session,
         &method_data.url,
         &method_data.parameters,
+        body::from_app(app)?,
     )
 }
 
-pub fn perform(
+pub fn prepare(
     app: &App,
     method: reqwest::Method,
     session: &mut Option<Session>,
     raw_url: &str,
     parameters: &Vec<Parameter>,
-) -> HurlResult<Response> {
-    let client = Client::new();
+    body: Option<RawBody>,
+) -> HurlResult<Prepared> {
+    prepare_request(app, method, session, raw_url, parameters, body, true)
+}
+
+/// Prepare a request which is only written out, without looking up
+/// credentials, so nothing is prompted for, run or fetched.
+pub fn prepare_without_auth(
+    app: &App,
+    method: reqwest::Method,
+    raw_url: &str,
+    parameters: &Vec<Parameter>,
+    body: Option<RawBody>,
+) -> HurlResult<Prepared> {
+    prepare_request(app, method, &mut None, raw_url, parameters, body, false)
+}
+
+fn prepare_request(
+    app: &App,
+    method: reqwest::Method,
+    session: &mut Option<Session>,
+    raw_url: &str,
+    parameters: &Vec<Parameter>,
+    body: Option<RawBody>,
+    with_auth: bool,
+) -> HurlResult<Prepared> {
+    let redirects = Redirects::default();
+    let client = build_client(app, &redirects)?;
     let url = parse(app, raw_url)?;
     debug!("Parsed url: {}", url);
+    let entry = history::Entry::new(
+        app,
+        &method,
+        &url,
+        session.as_ref(),
+        parameters,
+        body.as_ref(),
+    );
 
+    if body.is_some() && parameters.iter().any(|p| p.is_data()) {
+        return Err(Error::RawBodyWithData);
+    }
     let is_multipart = parameters.iter().any(|p| p.is_form_file());
     if is_multipart {
         trace!("Making multipart request because form file was given");
@@ -44,73 +146,230 @@ pub fn perform(
         }
     }
 
-    let mut builder = client.request(method, url);
-    builder = handle_session(
-        builder,
-        session,
-        parameters,
-        !app.read_only,
-        &app.auth,
-        &app.token,
-    );
-    builder = handle_parameters(builder, app.form, is_multipart, parameters)?;
-    builder = handle_auth(builder, &app.auth, &app.token)?;
-
-    if log_enabled!(log::Level::Info) {
-        let start = Instant::now();
-        let result = builder.send().map_err(From::from);
-        let elapsed = start.elapsed();
-        info!("Elapsed time: {:?}", elapsed);
-        result
+    let mut builder = client.request(method, url.clone());
+    builder = handle_session(app, builder, &url, session, parameters);
+    let (mut builder, data) = handle_parameters(builder, app.form, is_multipart, parameters)?;
+    let body = match body {
+        Some(body) => {
+            trace!("Adding raw body of {} bytes", body.bytes.len());
+            Some(body)
+        }
+        None => data,
+    };
+    if let Some(body) = &body {
+        builder = builder.body(body.bytes.clone());
+    }
+    let auth = if !with_auth {
+        None
+    } else if oauth::is_enabled(app) {
+        // With OAuth2 the credentials are for the token endpoint instead.
+        let token = oauth::access_token(app, session)?;
+        builder = handle_auth(builder, Some(AuthType::Bearer), &None, &token)?;
+        None
+    } else {
+        let auth = credentials::resolve(app, &url)?;
+        builder = handle_auth(builder, app.auth_type, &auth, &app.token)?;
+        auth
+    };
+    if let Some(offset) = download::resume_offset(app)? {
+        trace!("Resuming download from byte {}", offset);
+        builder = builder.header(RANGE, format!("bytes={}-", offset));
+    }
+
+    let mut request = builder.build()?;
+    if let Some(content_type) = body.as_ref().and_then(|b| b.content_type) {
+        if !request.headers().contains_key(CONTENT_TYPE) {
+            request
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        }
+    }
+    if with_auth && app.auth_type == Some(AuthType::SigV4) && !oauth::is_enabled(app) {
+        // Signed last, as the signature covers the final headers and body.
+        let bytes = body.as_ref().map(|b| b.bytes.as_slice());
+        auth::sign_v4(app, &auth, &mut request, bytes, is_multipart)?;
+    }
+    let parts = if is_multipart {
+        parameters
+            .iter()
+            .filter_map(|p| match p {
+                Parameter::Data { key, value } => Some(Part::Text(key.clone(), value.clone())),
+                Parameter::FormFile { key, filename } => {
+                    Some(Part::File(key.clone(), filename.clone()))
+                }
+                _ => None,
+            })
+            .collect()
     } else {
-        builder.send().map_err(From::from)
+        Vec::new()
+    };
+    Ok(Prepared {
+        client,
+        request,
+        body: body.map(|b| b.bytes),
+        parts,
+        entry,
+        auth,
+        redirects,
+    })
+}
+
+/// Build a client for the options of `app`, which adds the redirects it
+/// follows to `redirects`.
+pub fn build_client(app: &App, redirects: &Redirects) -> HurlResult<Client> {
+    let mut builder = Client::builder();
+    if !app.default_headers.is_empty() {
+        let mut headers = HeaderMap::new();
+        for (key, value) in app.default_headers.iter() {
+            trace!("Adding default header: {}", key);
+            let name = HeaderName::from_bytes(key.as_bytes())
+                .map_err(|_| Error::InvalidHeader(key.clone()))?;
+            let value =
+                HeaderValue::from_str(value).map_err(|_| Error::InvalidHeader(key.clone()))?;
+            headers.insert(name, value);
+        }
+        builder = builder.default_headers(headers);
+    }
+    if let Some(timeout) = app.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(timeout) = app.connect_timeout {
+        builder = builder.connect_timeout(timeout);
     }
+    builder = builder.redirect(redirect_policy(app, Arc::clone(redirects)));
+    if let Some(proxy) = &app.proxy {
+        trace!("Using proxy: {}", proxy);
+        let proxy = Proxy::all(proxy.as_str()).map_err(|_| Error::InvalidProxy(proxy.clone()))?;
+        builder = builder.proxy(proxy);
+    }
+    if let Some(path) = &app.cacert {
+        for cert in tls::ca_certificates(path)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if let Some(path) = &app.cert {
+        builder = builder.identity(tls::identity(path, app.key.as_deref())?);
+    }
+    if app.insecure {
+        builder = builder
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true);
+    }
+    builder.build().map_err(From::from)
 }
 
+/// Follow up to --max-redirects redirects with --follow, printing each one
+/// with -v, or return the redirect response as it is.
+fn redirect_policy(app: &App, redirects: Redirects) -> RedirectPolicy {
+    if !app.follow {
+        return RedirectPolicy::none();
+    }
+    let max = app.max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS);
+    let show = app.verbose > 0 && !app.quiet;
+    RedirectPolicy::custom(move |attempt| {
+        let hops = attempt.previous().len();
+        if hops > max {
+            return attempt.too_many_redirects();
+        }
+        if let Some(from) = attempt.previous().last() {
+            if show {
+                eprintln!("Redirect {}: {} {} -> {}", hops, attempt.status(), from, attempt.url());
+            }
+            if let Ok(mut redirects) = redirects.lock() {
+                redirects.push(Redirect {
+                    status: attempt.status().as_u16(),
+                    from: from.to_string(),
+                    to: attempt.url().to_string(),
+                });
+            }
+        }
+        attempt.follow()
+    })
+}
+
+/// Add the credentials as --auth-type says. Digest and sigv4 need the
+/// final request, so they only check that there are credentials here.
 fn handle_auth(
     mut builder: RequestBuilder,
+    auth_type: Option<AuthType>,
     auth: &Option<String>,
     token: &Option<String>,
 ) -> HurlResult<RequestBuilder> {
-    if let Some(auth_string) = auth {
-        let (username, maybe_password) = parse_auth(&auth_string)?;
-        trace!("Parsed basic authentication. Username={}", username);
-        builder = builder.basic_auth(username, maybe_password);
-    }
-    if let Some(bearer) = token {
-        trace!("Parsed bearer authentication. Token={}", bearer);
-        builder = builder.bearer_auth(bearer);
+    match auth_type {
+        None => {
+            if auth.is_some() {
+                builder = basic_auth(builder, auth)?;
+            }
+            if token.is_some() {
+                builder = bearer_auth(builder, token)?;
+            }
+        }
+        Some(AuthType::Basic) => builder = basic_auth(builder, auth)?,
+        Some(AuthType::Bearer) => builder = bearer_auth(builder, token)?,
+        Some(AuthType::ApiKey) => {
+            let key = auth
+                .as_ref()
+                .ok_or(Error::MissingCredentials(AuthType::ApiKey))?;
+            trace!("Adding API key");
+            builder = auth::api_key(builder, key)?;
+        }
+        Some(auth_type @ AuthType::Digest) | Some(auth_type @ AuthType::SigV4) => {
+            if auth.is_none() {
+                return Err(Error::MissingCredentials(auth_type));
+            }
+        }
     }
     Ok(builder)
 }
 
+fn basic_auth(builder: RequestBuilder, auth: &Option<String>) -> HurlResult<RequestBuilder> {
+    let auth_string = auth
+        .as_ref()
+        .ok_or(Error::MissingCredentials(AuthType::Basic))?;
+    let (username, maybe_password) = parse_auth(auth_string)?;
+    trace!("Parsed basic authentication. Username={}", username);
+    Ok(builder.basic_auth(username, maybe_password))
+}
+
+fn bearer_auth(builder: RequestBuilder, token: &Option<String>) -> HurlResult<RequestBuilder> {
+    let bearer = token
+        .as_ref()
+        .ok_or(Error::MissingCredentials(AuthType::Bearer))?;
+    trace!("Parsed bearer authentication. Token={}", bearer);
+    Ok(builder.bearer_auth(bearer))
+}
+
 fn handle_session(
+    app: &App,
     mut builder: RequestBuilder,
+    url: &Url,
     session: &mut Option<Session>,
     parameters: &Vec<Parameter>,
-    update_session: bool,
-    auth: &Option<String>,
-    token: &Option<String>,
 ) -> RequestBuilder {
     if let Some(s) = session {
         trace!("Adding session data to request");
-        builder = s.add_to_request(builder);
-        if update_session {
+        builder = s.add_to_request(builder, url);
+        if !app.read_only {
             trace!("Updating session with parameters from this request");
             s.update_with_parameters(parameters);
-            s.update_auth(auth, token);
+            s.update_auth(app.auth_type, &app.auth, &app.token);
         }
     }
     builder
 }
 
+/// Add the parameters to the request, returning any data parameters
+/// serialized as a body.
+///
+/// A multipart body is streamed from its files, so it is added to the
+/// builder directly rather than returned.
 fn handle_parameters(
     mut builder: RequestBuilder,
     is_form: bool,
     is_multipart: bool,
     parameters: &Vec<Parameter>,
-) -> HurlResult<RequestBuilder> {
-    let mut data: HashMap<&String, Value> = HashMap::new();
+) -> HurlResult<(RequestBuilder, Option<RawBody>)> {
+    let mut data: Map<String, Value> = Map::new();
     let mut multipart = if is_multipart {
         Some(Form::new())
     } else {
@@ -118,6 +377,7 @@ fn handle_parameters(
     };
 
     for param in parameters.iter() {
+        let invalid = |e: Error| Error::Parameter(param.to_string(), Box::new(e));
         match param {
             Parameter::Header { key, value } => {
                 trace!("Assing header: {}", key);
@@ -126,7 +386,7 @@ fn handle_parameters(
             Parameter::Data { key, value } => {
                 trace!("Adding data: {}", key);
                 if multipart.is_none() {
-                    data.insert(key, Value::String(value.to_owned()));
+                    datapath::insert(&mut data, key, Value::String(value.to_owned()))?;
                 } else {
                     multipart = multipart.map(|m| m.text(key.to_owned(), value.to_owned()));
                 }
@@ -137,27 +397,30 @@ fn handle_parameters(
             }
             Parameter::RawJsonData { key, value } => {
                 trace!("Adding JSON data: {}", key);
-                let v: Value = serde_json::from_str(value)?;
-                data.insert(key, v);
+                let v: Value = serde_json::from_str(value).map_err(|e| invalid(e.into()))?;
+                datapath::insert(&mut data, key, v)?;
             }
             Parameter::RawJsonDataFile { key, filename } => {
                 trace!("Adding JSON data for key={} from file={}", key, filename);
-                let file = File::open(filename)?;
+                let file = File::open(filename)
+                    .map_err(|e| invalid(Error::File(filename.into(), e)))?;
                 let reader = BufReader::new(file);
-                let v: Value = serde_json::from_reader(reader)?;
-                data.insert(key, v);
+                let v: Value = serde_json::from_reader(reader).map_err(|e| invalid(e.into()))?;
+                datapath::insert(&mut data, key, v)?;
             }
             Parameter::DataFile { key, filename } => {
                 trace!("Adding data from file={} for key={}", filename, key);
-                let value = std::fs::read_to_string(filename)?;
-                data.insert(key, Value::String(value));
+                let value = std::fs::read_to_string(filename)
+                    .map_err(|e| invalid(Error::File(filename.into(), e)))?;
+                datapath::insert(&mut data, key, Value::String(value))?;
             }
             Parameter::FormFile { key, filename } => {
                 trace!("Adding file={} with key={}", filename, key);
                 multipart = Some(
                     multipart
                         .unwrap()
-                        .file(key.to_owned(), filename.to_owned())?,
+                        .file(key.to_owned(), filename.to_owned())
+                        .map_err(|e| invalid(Error::File(filename.into(), e)))?,
                 );
             }
         }
@@ -168,17 +431,38 @@ fn handle_parameters(
     } else {
         if !data.is_empty() {
             if is_form {
-                builder = builder.form(&data);
+                let body = serde_urlencoded::to_string(&datapath::flatten(&data))
+                    .map_err(Error::FormEncoding)?;
+                let body = RawBody {
+                    bytes: body.into_bytes(),
+                    content_type: Some("application/x-www-form-urlencoded"),
+                };
+                return Ok((builder, Some(body)));
             } else {
-                builder = builder.json(&data);
+                let body = RawBody {
+                    bytes: serde_json::to_vec(&data)?,
+                    content_type: Some("application/json"),
+                };
+                return Ok((builder, Some(body)));
             }
         }
     }
 
-    Ok(builder)
+    Ok((builder, None))
+}
+
+/// Parse a URL from the command line, which may leave out the scheme or be
+/// relative to the base_url of the profile.
+pub fn parse(app: &App, s: &str) -> HurlResult<Url> {
+    resolve(app, s).map_err(|e| Error::UrlParseError(s.to_owned(), e))
 }
 
-fn parse(app: &App, s: &str) -> Result<Url, reqwest::UrlError> {
+fn resolve(app: &App, s: &str) -> Result<Url, reqwest::UrlError> {
+    if s.starts_with('/') {
+        if let Some(base) = &app.base_url {
+            return Url::parse(&format!("{}{}", base.trim_end_matches('/'), s));
+        }
+    }
     if s.starts_with(":/") {
         return Url::parse(&format!("http://localhost{}", &s[1..]));
     } else if s.starts_with(":") {
@@ -196,7 +480,7 @@ fn parse(app: &App, s: &str) -> Result<Url, reqwest::UrlError> {
     }
 }
 
-fn parse_auth(s: &str) -> HurlResult<(String, Option<String>)> {
+pub fn parse_auth(s: &str) -> HurlResult<(String, Option<String>)> {
     if let Some(idx) = s.find(':') {
         let (username, password_with_colon) = s.split_at(idx);
         let password = password_with_colon.trim_start_matches(':');
@@ -206,7 +490,8 @@ fn parse_auth(s: &str) -> HurlResult<(String, Option<String>)> {
             return Ok((username.to_owned(), Some(password.to_owned())));
         }
     } else {
-        let password = rpassword::read_password_from_tty(Some("Password: "))?;
+        let password = credentials::read_password("Password: ")?
+            .ok_or_else(|| Error::PasswordRequired(s.to_owned()))?;
         return Ok((s.to_owned(), Some(password)));
     }
 }
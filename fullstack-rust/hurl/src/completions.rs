@@ -0,0 +1,133 @@
+use crate::app::{App, CompletionsData};
+use crate::config;
+use crate::errors::HurlResult;
+use crate::session::Session;
+use std::io::{self, Write};
+use structopt::clap::Shell;
+use structopt::StructOpt;
+
+/// Completion for --session and --profile in bash, which wraps the function
+/// generated by clap.
+const BASH_NAMES: &str = r#"
+_hurl_names() {
+    case "${COMP_WORDS[COMP_CWORD-1]}" in
+        --session)
+            COMPREPLY=($(compgen -W "$(hurl completions --list sessions 2>/dev/null)" -- "${COMP_WORDS[COMP_CWORD]}"))
+            return 0
+            ;;
+        --profile)
+            COMPREPLY=($(compgen -W "$(hurl completions --list profiles 2>/dev/null)" -- "${COMP_WORDS[COMP_CWORD]}"))
+            return 0
+            ;;
+    esac
+    _hurl "$@"
+}
+
+complete -F _hurl_names -o bashdefault -o default hurl
+"#;
+
+const ZSH_NAMES: &str = r#"
+_hurl_sessions() {
+    local -a names
+    names=(${(f)"$(hurl completions --list sessions 2>/dev/null)"})
+    _describe 'session' names
+}
+
+_hurl_profiles() {
+    local -a names
+    names=(${(f)"$(hurl completions --list profiles 2>/dev/null)"})
+    _describe 'profile' names
+}
+"#;
+
+const FISH_NAMES: &str = r#"
+complete -c hurl -l session -x -a "(hurl completions --list sessions 2>/dev/null)"
+complete -c hurl -l profile -x -a "(hurl completions --list profiles 2>/dev/null)"
+"#;
+
+pub fn run(app: &App, data: &CompletionsData) -> HurlResult<()> {
+    if let Some(list) = &data.list {
+        let names = match list.as_str() {
+            "sessions" => session_names(app),
+            _ => profile_names(app),
+        };
+        for name in names.iter() {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
+    let shell = match data.shell.as_deref() {
+        Some("zsh") => Shell::Zsh,
+        Some("fish") => Shell::Fish,
+        _ => Shell::Bash,
+    };
+    let mut script = Vec::new();
+    App::clap().gen_completions_to("hurl", shell, &mut script);
+    let script = String::from_utf8_lossy(&script);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match shell {
+        Shell::Bash => {
+            write!(out, "{}", script)?;
+            write!(out, "{}", BASH_NAMES)?;
+        }
+        Shell::Zsh => {
+            // The names have to be completed by functions defined before
+            // clap's `_hurl "$@"` call at the end of the script.
+            let script = with_action(&script, "--session", "_hurl_sessions");
+            let script = with_action(&script, "--profile", "_hurl_profiles");
+            let (head, tail) = match script.rfind("_hurl \"$@\"") {
+                Some(idx) => script.split_at(idx),
+                None => (script.as_str(), ""),
+            };
+            write!(out, "{}{}\n{}", head, ZSH_NAMES, tail)?;
+        }
+        _ => {
+            write!(out, "{}", script)?;
+            write!(out, "{}", FISH_NAMES)?;
+        }
+    }
+    Ok(())
+}
+
+/// Give an option in a zsh `_arguments` spec, e.g. `'--session=[Session
+/// name]' \`, an action which completes its value.
+fn with_action(script: &str, option: &str, action: &str) -> String {
+    let prefix = format!("'{}=[", option);
+    script
+        .lines()
+        .map(|line| {
+            if !line.trim_start().starts_with(&prefix) {
+                return line.to_owned();
+            }
+            match line.rfind("]' \\") {
+                Some(idx) => format!("{}]: :{}' \\", &line[..idx], action),
+                None => line.to_owned(),
+            }
+        })
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// The distinct names of all stored sessions.
+fn session_names(app: &App) -> Vec<String> {
+    let mut names: Vec<String> = Session::all(app)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|s| s.name().to_owned())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// The names of the profiles in the configuration file.
+fn profile_names(app: &App) -> Vec<String> {
+    let mut names: Vec<String> = config::load(&config::config_file(app))
+        .map(|(config, _)| config.profile.into_keys().collect())
+        .unwrap_or_default();
+    names.sort();
+    names
+}
@@ -1,9 +1,78 @@
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 
-use crate::app::App;
+use crate::app::{App, ConfigCommand};
+use crate::auth::AuthType;
 use crate::directories::DIRECTORIES;
+use crate::errors::{Error, HurlResult};
+use crate::secrets::{mask, mask_auth};
+use crate::session::is_sensitive_header;
+
+const KEYS: &[&str] = &[
+    "verbose",
+    "form",
+    "auth",
+    "token",
+    "auth_command",
+    "netrc",
+    "secure",
+    "key_file",
+    "encrypt_secrets",
+    "no_store_secrets",
+    "default_profile",
+    "profile",
+];
+
+const PROFILE_KEYS: &[&str] = &[
+    "base_url",
+    "headers",
+    "auth",
+    "token",
+    "auth_command",
+    "netrc",
+    "auth_type",
+    "aws_region",
+    "aws_service",
+    "secure",
+    "timeout",
+    "connect_timeout",
+    "follow",
+    "max_redirects",
+    "proxy",
+    "cacert",
+    "cert",
+    "key",
+    "insecure",
+    "oauth_token_url",
+    "oauth_client_id",
+    "oauth_client_secret",
+    "oauth_scope",
+    "oauth_grant",
+];
+
+/// Where the value of a setting came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    CommandLine,
+    Environment,
+    Profile,
+    File,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::CommandLine => write!(f, "command line"),
+            Source::Environment => write!(f, "environment"),
+            Source::Profile => write!(f, "profile"),
+            Source::File => write!(f, "config file"),
+        }
+    }
+}
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
@@ -11,20 +80,219 @@ pub struct Config {
     pub form: Option<bool>,
     pub auth: Option<String>,
     pub token: Option<String>,
+    pub auth_command: Option<String>,
+    pub netrc: Option<bool>,
     pub secure: Option<bool>,
+    pub key_file: Option<PathBuf>,
+    pub encrypt_secrets: Option<bool>,
+    pub no_store_secrets: Option<bool>,
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profile: HashMap<String, Profile>,
+}
+
+/// A named set of options, e.g. `[profile.staging]`, which take precedence
+/// over the top level of the configuration file when selected.
+#[derive(Debug, Default, Deserialize)]
+pub struct Profile {
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub auth: Option<String>,
+    pub token: Option<String>,
+    pub auth_command: Option<String>,
+    pub netrc: Option<bool>,
+    pub auth_type: Option<AuthType>,
+    pub aws_region: Option<String>,
+    pub aws_service: Option<String>,
+    pub secure: Option<bool>,
+    pub timeout: Option<f64>,
+    pub connect_timeout: Option<f64>,
+    pub follow: Option<bool>,
+    pub max_redirects: Option<usize>,
+    pub proxy: Option<String>,
+    pub cacert: Option<PathBuf>,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub insecure: Option<bool>,
+    pub oauth_token_url: Option<String>,
+    pub oauth_client_id: Option<String>,
+    pub oauth_client_secret: Option<String>,
+    pub oauth_scope: Option<String>,
+    pub oauth_grant: Option<String>,
 }
 
 pub fn config_file(app: &App) -> PathBuf {
     app.config
         .as_ref()
         .cloned()
-        .filter(|config_path| config_path.is_file())
         .unwrap_or_else(|| DIRECTORIES.config().join("config"))
 }
 
-pub fn read_config_file(path: PathBuf) -> Option<Config> {
-    fs::read_to_string(path).ok().map(|content| {
-        let config: Config = toml::from_str(&content).unwrap();
-        config
-    })
+/// Read the configuration file, printing a warning for each unknown key.
+///
+/// A missing file is only an error if it is `required`, i.e. it was named
+/// explicitly rather than being the default location.
+pub fn read_config_file(path: &Path, required: bool) -> HurlResult<Option<Config>> {
+    match load(path) {
+        Ok((config, warnings)) => {
+            for warning in warnings.iter() {
+                eprintln!("Warning: {}", warning);
+            }
+            Ok(Some(config))
+        }
+        Err(ref e) if is_not_found(e) && !required => Ok(None),
+        Err(ref e) if is_not_found(e) => Err(Error::ConfigNotFound(path.to_owned())),
+        Err(e) => Err(e),
+    }
+}
+
+/// Parse the configuration file, returning it along with warnings about
+/// any keys which hurl does not know about.
+pub fn load(path: &Path) -> HurlResult<(Config, Vec<String>)> {
+    let content = fs::read_to_string(path).map_err(|e| Error::File(path.to_owned(), e))?;
+    let config: Config = toml::from_str(&content).map_err(|e| config_error(path, e))?;
+    let value: toml::Value = toml::from_str(&content).map_err(|e| config_error(path, e))?;
+    Ok((config, unknown_keys(path, &value)))
+}
+
+fn is_not_found(err: &Error) -> bool {
+    match err {
+        Error::File(_, e) => e.kind() == io::ErrorKind::NotFound,
+        _ => false,
+    }
+}
+
+fn config_error(path: &Path, err: toml::de::Error) -> Error {
+    let position = err.line_col().map(|(line, col)| (line + 1, col + 1));
+    let mut message = err.to_string();
+    if let Some(idx) = message.rfind(" at line ") {
+        message.truncate(idx);
+    }
+    Error::Config(path.to_owned(), position, message)
+}
+
+fn unknown_keys(path: &Path, value: &toml::Value) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let table = match value.as_table() {
+        Some(table) => table,
+        None => return warnings,
+    };
+    for (key, value) in table.iter() {
+        if !KEYS.contains(&key.as_str()) {
+            warnings.push(format!("unknown key `{}` in {}", key, path.display()));
+        } else if key == "profile" {
+            let profiles = value.as_table().into_iter().flat_map(|t| t.iter());
+            for (name, profile) in profiles {
+                let keys = profile.as_table().into_iter().flat_map(|t| t.keys());
+                for key in keys.filter(|k| !PROFILE_KEYS.contains(&k.as_str())) {
+                    warnings.push(format!(
+                        "unknown key `{}` in profile {} in {}",
+                        key,
+                        name,
+                        path.display()
+                    ));
+                }
+            }
+        }
+    }
+    warnings
+}
+
+pub fn run_command(app: &mut App, cmd: &ConfigCommand) -> HurlResult<()> {
+    let path = config_file(app);
+    match cmd {
+        ConfigCommand::Path => {
+            println!("{}", path.display());
+            Ok(())
+        }
+        ConfigCommand::Check => {
+            let (config, warnings) = match load(&path) {
+                Ok(loaded) => loaded,
+                Err(ref e) if is_not_found(e) && app.config.is_none() => {
+                    println!("No configuration file at {}", path.display());
+                    return Ok(());
+                }
+                Err(ref e) if is_not_found(e) => return Err(Error::ConfigNotFound(path)),
+                Err(e) => return Err(e),
+            };
+            for warning in warnings.iter() {
+                println!("Warning: {}", warning);
+            }
+            let profile = app.profile.as_ref().or(config.default_profile.as_ref());
+            if let Some(name) = profile {
+                if !config.profile.contains_key(name) {
+                    return Err(Error::UnknownProfile(name.clone()));
+                }
+            }
+            println!("{} is valid", path.display());
+            Ok(())
+        }
+        ConfigCommand::Show { show_secrets } => {
+            app.process_config_file()?;
+            let show = *show_secrets;
+            let exists = if path.is_file() { "" } else { " (not found)" };
+            println!("# {}{}", path.display(), exists);
+
+            let mut rows: Vec<(String, String, &'static str)> = Vec::new();
+            let mut row = |key: &'static str, value: Option<String>| {
+                if let Some(value) = value {
+                    rows.push((key.to_owned(), value, key));
+                }
+            };
+            row("profile", app.profile.clone());
+            row("verbose", Some(app.verbose.to_string()));
+            row("form", Some(app.form.to_string()));
+            row("secure", Some(app.secure.to_string()));
+            row("auth", app.auth.as_ref().map(|a| mask_auth(a, show)));
+            row("token", app.token.as_ref().map(|t| mask(t, show)));
+            row("auth_command", app.auth_command.clone());
+            row("netrc", Some(app.netrc.to_string()));
+            row("auth_type", app.auth_type.map(|t| t.to_string()));
+            row("aws_region", app.aws_region.clone());
+            row("aws_service", app.aws_service.clone());
+            row("base_url", app.base_url.clone());
+            row("timeout", app.timeout.map(|t| format!("{}s", t.as_secs_f64())));
+            row(
+                "connect_timeout",
+                app.connect_timeout.map(|t| format!("{}s", t.as_secs_f64())),
+            );
+            row("follow", Some(app.follow.to_string()));
+            row("max_redirects", app.max_redirects.map(|m| m.to_string()));
+            row("proxy", app.proxy.clone());
+            row("cacert", app.cacert.as_ref().map(|c| c.display().to_string()));
+            row("cert", app.cert.as_ref().map(|c| c.display().to_string()));
+            row("key", app.key.as_ref().map(|k| k.display().to_string()));
+            row("insecure", Some(app.insecure.to_string()));
+            row("oauth_token_url", app.oauth_token_url.clone());
+            row("oauth_client_id", app.oauth_client_id.clone());
+            row(
+                "oauth_client_secret",
+                app.oauth_client_secret.as_ref().map(|s| mask(s, show)),
+            );
+            row("oauth_scope", app.oauth_scope.clone());
+            row("oauth_grant", app.oauth_grant.clone());
+            row(
+                "key_file",
+                app.key_file.as_ref().map(|k| k.display().to_string()),
+            );
+            row("encrypt_secrets", Some(app.encrypt_secrets.to_string()));
+            row("no_store_secrets", Some(app.no_store_secrets.to_string()));
+            for (key, value) in app.default_headers.iter() {
+                let value = mask(value, show || !is_sensitive_header(key));
+                rows.push((format!("headers.{}", key), value, "headers"));
+            }
+
+            let width = rows.iter().map(|(k, _, _)| k.len()).max().unwrap_or(0);
+            for (key, value, source_key) in rows.iter() {
+                let source = app
+                    .sources
+                    .get(source_key)
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| "default".to_owned());
+                println!("{:width$} = {}  # {}", key, value, source, width = width);
+            }
+            Ok(())
+        }
+    }
 }
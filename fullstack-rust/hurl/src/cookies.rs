@@ -0,0 +1,386 @@
+use log::trace;
+use reqwest::cookie::Cookie;
+use reqwest::Url;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A cookie as stored in a session, following the storage model of RFC 6265.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredCookie {
+    pub name: String,
+    pub value: String,
+    /// The domain the cookie belongs to. Empty for cookies read from
+    /// sessions saved before domains were recorded, until the session gives
+    /// them its own host with `CookieJar::adopt_legacy`.
+    pub domain: String,
+    pub host_only: bool,
+    pub path: String,
+    /// Expiry as seconds since the Unix epoch, or `None` for a cookie which
+    /// lasts as long as the session.
+    pub expires: Option<u64>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: Option<String>,
+    pub created: u64,
+}
+
+impl StoredCookie {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires.map(|e| e <= now).unwrap_or(false)
+    }
+
+    fn is_legacy(&self) -> bool {
+        self.domain.is_empty()
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            Some(h) => h.to_ascii_lowercase(),
+            None => return false,
+        };
+        if self.secure && url.scheme() != "https" {
+            return false;
+        }
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(&host, &self.domain)
+        };
+        domain_ok && path_match(url.path(), &self.path)
+    }
+}
+
+/// The cookies belonging to a session.
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(transparent)]
+pub struct CookieJar {
+    cookies: Vec<StoredCookie>,
+}
+
+impl CookieJar {
+    pub fn iter(&self) -> impl Iterator<Item = &StoredCookie> {
+        self.cookies.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut StoredCookie> {
+        self.cookies.iter_mut()
+    }
+
+    /// Store a cookie received in a response to a request for `url`.
+    ///
+    /// A cookie replaces any existing cookie with the same name, domain and
+    /// path, and a cookie which has already expired removes it instead.
+    pub fn store(&mut self, cookie: &Cookie, url: &Url) {
+        let now = now();
+        let host = match url.host_str() {
+            Some(h) => h.to_ascii_lowercase(),
+            None => return,
+        };
+
+        let (domain, host_only) = match cookie.domain().map(|d| d.trim_start_matches('.')) {
+            Some(d) if !d.is_empty() => {
+                let d = d.to_ascii_lowercase();
+                if !domain_match(&host, &d) {
+                    trace!("Ignoring cookie {} for foreign domain {}", cookie.name(), d);
+                    return;
+                }
+                (d, false)
+            }
+            _ => (host, true),
+        };
+        let path = match cookie.path() {
+            Some(p) if p.starts_with('/') => p.to_owned(),
+            _ => default_path(url.path()),
+        };
+        let expires = match (cookie.max_age(), cookie.expires()) {
+            (Some(max_age), _) => Some(now + max_age.as_secs()),
+            (None, Some(at)) => Some(
+                at.duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+            ),
+            (None, None) => None,
+        };
+        let same_site = if cookie.same_site_strict() {
+            Some("Strict".to_owned())
+        } else if cookie.same_site_lax() {
+            Some("Lax".to_owned())
+        } else {
+            None
+        };
+
+        let mut created = now;
+        self.cookies.retain(|c| {
+            let replaced = c.name == cookie.name() && c.domain == domain && c.path == path;
+            if replaced {
+                created = created.min(c.created);
+            }
+            !replaced
+        });
+
+        let stored = StoredCookie {
+            name: cookie.name().to_owned(),
+            value: cookie.value().to_owned(),
+            domain,
+            host_only,
+            path,
+            expires,
+            secure: cookie.secure(),
+            http_only: cookie.http_only(),
+            same_site,
+            created,
+        };
+        if stored.is_expired(now) {
+            trace!("Removing expired cookie {}", stored.name);
+            return;
+        }
+        self.cookies.push(stored);
+    }
+
+    /// Give cookies without a domain to `host`, the host of the session
+    /// they were stored in, so they are only sent back to it.
+    pub fn adopt_legacy(&mut self, host: &str) {
+        let host = host.to_ascii_lowercase();
+        for cookie in self.cookies.iter_mut().filter(|c| c.is_legacy()) {
+            cookie.domain = host.clone();
+            cookie.host_only = true;
+        }
+    }
+
+    pub fn remove_expired(&mut self) {
+        let now = now();
+        self.cookies.retain(|c| !c.is_expired(now));
+    }
+
+    /// The value of the `Cookie` header to send with a request for `url`.
+    ///
+    /// hurl has no notion of a browsing context, so every request counts as
+    /// a same-site request and SameSite never withholds a cookie.
+    pub fn header_for(&self, url: &Url) -> Option<String> {
+        let now = now();
+        let mut matching: Vec<&StoredCookie> = self
+            .cookies
+            .iter()
+            .filter(|c| !c.is_expired(now) && c.matches(url))
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+        matching.sort_by(|a, b| {
+            b.path
+                .len()
+                .cmp(&a.path.len())
+                .then(a.created.cmp(&b.created))
+        });
+        Some(
+            matching
+                .iter()
+                .map(|c| format!("{}={}", c.name, c.value))
+                .collect::<Vec<String>>()
+                .join("; "),
+        )
+    }
+}
+
+/// Sessions saved by older versions of hurl store cookies as a list of
+/// `(name, value)` pairs; those become cookies without a domain.
+pub fn deserialize_jar<'de, D>(deserializer: D) -> Result<CookieJar, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Jar(Vec<StoredCookie>),
+        Legacy(Vec<(String, String)>),
+    }
+
+    let cookies = match Stored::deserialize(deserializer)? {
+        Stored::Jar(cookies) => cookies,
+        Stored::Legacy(pairs) => {
+            let created = now();
+            let mut cookies: Vec<StoredCookie> = Vec::new();
+            for (name, value) in pairs {
+                // Later pairs were appended by later responses, so they win.
+                cookies.retain(|c| c.name != name);
+                cookies.push(StoredCookie {
+                    name,
+                    value,
+                    domain: String::new(),
+                    host_only: false,
+                    path: "/".to_owned(),
+                    expires: None,
+                    secure: false,
+                    http_only: false,
+                    same_site: None,
+                    created,
+                });
+            }
+            cookies
+        }
+    };
+    let mut jar = CookieJar { cookies };
+    jar.remove_expired();
+    Ok(jar)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    host.parse::<IpAddr>().is_err()
+        && host.ends_with(domain)
+        && host[..host.len() - domain.len()].ends_with('.')
+}
+
+fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    if request_path == cookie_path {
+        return true;
+    }
+    request_path.starts_with(cookie_path)
+        && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/'))
+}
+
+fn default_path(request_path: &str) -> String {
+    match request_path.rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(idx) => request_path[..idx].to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receive(jar: &mut CookieJar, url: &str, set_cookie: &str) {
+        let resp: reqwest::Response = http::Response::builder()
+            .header("set-cookie", set_cookie)
+            .body("")
+            .unwrap()
+            .into();
+        for cookie in resp.cookies() {
+            jar.store(&cookie, &Url::parse(url).unwrap());
+        }
+    }
+
+    fn header(jar: &CookieJar, url: &str) -> Option<String> {
+        jar.header_for(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn matches_domains() {
+        assert!(domain_match("example.com", "example.com"));
+        assert!(domain_match("www.example.com", "example.com"));
+        assert!(!domain_match("badexample.com", "example.com"));
+        assert!(!domain_match("example.com", "www.example.com"));
+        assert!(!domain_match("1.2.3.4", "2.3.4"));
+    }
+
+    #[test]
+    fn matches_paths() {
+        assert!(path_match("/", "/"));
+        assert!(path_match("/a/b", "/a"));
+        assert!(path_match("/a/b", "/a/"));
+        assert!(!path_match("/ab", "/a"));
+        assert!(!path_match("/", "/a"));
+        assert_eq!(default_path("/a/b"), "/a");
+        assert_eq!(default_path("/a"), "/");
+        assert_eq!(default_path(""), "/");
+    }
+
+    #[test]
+    fn sends_cookies_to_their_domain() {
+        let mut jar = CookieJar::default();
+        receive(&mut jar, "http://www.example.com/", "host=1");
+        receive(
+            &mut jar,
+            "http://www.example.com/",
+            "wide=2; Domain=.example.com",
+        );
+        receive(
+            &mut jar,
+            "http://www.example.com/",
+            "foreign=3; Domain=other.com",
+        );
+
+        assert_eq!(
+            header(&jar, "http://www.example.com/"),
+            Some("host=1; wide=2".into())
+        );
+        assert_eq!(
+            header(&jar, "http://api.example.com/"),
+            Some("wide=2".into())
+        );
+        assert_eq!(header(&jar, "http://other.com/"), None);
+    }
+
+    #[test]
+    fn sends_cookies_under_their_path_longest_first() {
+        let mut jar = CookieJar::default();
+        receive(&mut jar, "http://example.com/", "root=1");
+        receive(&mut jar, "http://example.com/", "deep=2; Path=/api");
+        receive(&mut jar, "http://example.com/docs/page", "default=3");
+
+        assert_eq!(header(&jar, "http://example.com/"), Some("root=1".into()));
+        assert_eq!(
+            header(&jar, "http://example.com/api/x"),
+            Some("deep=2; root=1".into())
+        );
+        assert_eq!(
+            header(&jar, "http://example.com/docs/other"),
+            Some("default=3; root=1".into())
+        );
+    }
+
+    #[test]
+    fn replaces_and_expires_cookies() {
+        let mut jar = CookieJar::default();
+        receive(&mut jar, "http://example.com/", "a=1");
+        receive(&mut jar, "http://example.com/", "a=2");
+        receive(&mut jar, "http://example.com/", "b=1; Max-Age=3600");
+        assert_eq!(header(&jar, "http://example.com/"), Some("a=2; b=1".into()));
+        assert!(jar
+            .iter()
+            .find(|c| c.name == "b")
+            .unwrap()
+            .expires
+            .is_some());
+
+        receive(&mut jar, "http://example.com/", "a=gone; Max-Age=0");
+        receive(
+            &mut jar,
+            "http://example.com/",
+            "b=gone; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+        );
+        assert_eq!(header(&jar, "http://example.com/"), None);
+        assert_eq!(jar.iter().count(), 0);
+    }
+
+    #[test]
+    fn sends_secure_cookies_over_https_only() {
+        let mut jar = CookieJar::default();
+        receive(&mut jar, "https://example.com/", "s=1; Secure");
+        assert_eq!(header(&jar, "https://example.com/"), Some("s=1".into()));
+        assert_eq!(header(&jar, "http://example.com/"), None);
+    }
+
+    #[test]
+    fn gives_legacy_cookies_to_the_session_host() {
+        let mut jar: CookieJar =
+            deserialize_jar(serde_json::json!([["a", "1"], ["a", "2"]])).unwrap();
+        assert_eq!(header(&jar, "http://example.com/"), None);
+
+        jar.adopt_legacy("Example.com");
+        assert_eq!(header(&jar, "http://example.com/x"), Some("a=2".into()));
+        assert_eq!(header(&jar, "http://www.example.com/"), None);
+        assert_eq!(header(&jar, "http://other.com/"), None);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::app::App;
+use crate::auth::AuthType;
+use crate::errors::{Error, HurlResult};
+use log::{debug, trace};
+use reqwest::Url;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// The credentials for --auth to send to `url` with.
+///
+/// --auth given as a username alone gets its password from auth_command,
+/// from a matching login in ~/.netrc with --netrc, or else by asking for
+/// it. Without --auth or --token the login for the machine in ~/.netrc is
+/// used with --netrc, but never its default login. An API key is taken as
+/// it is.
+pub fn resolve(app: &App, url: &Url) -> HurlResult<Option<String>> {
+    if app.auth_type == Some(AuthType::ApiKey) || app.auth_type == Some(AuthType::Bearer) {
+        return Ok(app.auth.clone());
+    }
+    let host = url.host_str().unwrap_or("");
+    match &app.auth {
+        Some(auth) if auth.contains(':') => Ok(Some(auth.clone())),
+        Some(username) => {
+            if let Some(command) = &app.auth_command {
+                let password = run_auth_command(command)?;
+                return Ok(Some(format!("{}:{}", username, password)));
+            }
+            if app.netrc {
+                let login = netrc_login(host, true)?.filter(|l| &l.login == username);
+                if let Some(login) = login {
+                    debug!("Using the password for {} from netrc", host);
+                    return Ok(Some(format!("{}:{}", username, login.password)));
+                }
+            }
+            match read_password("Password: ")? {
+                Some(password) => Ok(Some(format!("{}:{}", username, password))),
+                None => Err(Error::PasswordRequired(username.clone())),
+            }
+        }
+        None if app.netrc && app.token.is_none() => Ok(netrc_login(host, false)?.map(|login| {
+            debug!("Using the login for {} from netrc", host);
+            format!("{}:{}", login.login, login.password)
+        })),
+        None => Ok(None),
+    }
+}
+
+/// Ask for a password on the terminal, or None when there is no terminal
+/// to ask on, so that jobs without one fail instead of waiting forever.
+pub fn read_password(prompt: &str) -> HurlResult<Option<String>> {
+    if !atty::is(atty::Stream::Stdin) && !atty::is(atty::Stream::Stderr) {
+        return Ok(None);
+    }
+    Ok(Some(rpassword::read_password_from_tty(Some(prompt))?))
+}
+
+/// Run auth_command with the shell and take the first line it prints as
+/// the password, in the way of `pass show`.
+fn run_auth_command(command: &str) -> HurlResult<String> {
+    trace!("Running auth_command {}", command);
+    let output = if cfg!(windows) {
+        Command::new("cmd").arg("/C").arg(command).output()
+    } else {
+        Command::new("sh").arg("-c").arg(command).output()
+    };
+    let output = output.map_err(|e| Error::AuthCommand(command.to_owned(), e.to_string()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = match stderr.lines().next() {
+            Some(line) if !line.trim().is_empty() => line.trim().to_owned(),
+            _ => output.status.to_string(),
+        };
+        return Err(Error::AuthCommand(command.to_owned(), reason));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.lines().next() {
+        Some(line) if !line.is_empty() => Ok(line.to_owned()),
+        _ => Err(Error::AuthCommand(
+            command.to_owned(),
+            "it printed no password".to_owned(),
+        )),
+    }
+}
+
+/// A login from a netrc file.
+struct Login {
+    login: String,
+    password: String,
+}
+
+fn netrc_path() -> Option<PathBuf> {
+    match std::env::var_os("NETRC") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => dirs::home_dir().map(|home| home.join(".netrc")),
+    }
+}
+
+/// The login for `host` in the netrc file, falling back to its default
+/// login if `or_default` is set.
+fn netrc_login(host: &str, or_default: bool) -> HurlResult<Option<Login>> {
+    let path = match netrc_path() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::File(path, e)),
+    };
+    Ok(parse_netrc(&content, host, or_default))
+}
+
+fn parse_netrc(content: &str, host: &str, or_default: bool) -> Option<Login> {
+    let mut tokens = Vec::new();
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        for word in line.split_whitespace() {
+            // A macro runs until the next empty line.
+            if word == "macdef" {
+                for line in lines.by_ref() {
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+                break;
+            }
+            tokens.push(word);
+        }
+    }
+
+    // Each entry is the machine it is for, or None for the default.
+    let mut entries: Vec<(Option<&str>, Option<&str>, Option<&str>)> = Vec::new();
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => entries.push((Some(tokens.next().unwrap_or("")), None, None)),
+            "default" => entries.push((None, None, None)),
+            "login" | "password" | "account" => {
+                let value = tokens.next();
+                if let Some(entry) = entries.last_mut() {
+                    match token {
+                        "login" => entry.1 = value,
+                        "password" => entry.2 = value,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let entry = entries
+        .iter()
+        .find(|e| e.0 == Some(host))
+        .or_else(|| entries.iter().find(|e| or_default && e.0.is_none()))?;
+    match entry {
+        (_, Some(login), Some(password)) => Some(Login {
+            login: login.to_string(),
+            password: password.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NETRC: &str = "\
+machine api.example.com
+  login alice
+  password s3cret
+
+machine other.example.com login bob account x password hunter2
+macdef init
+  machine api.example.com login mallory password evil
+  cd /pub
+
+default login anonymous password guest
+";
+
+    fn login(content: &str, host: &str, or_default: bool) -> Option<(String, String)> {
+        parse_netrc(content, host, or_default).map(|l| (l.login, l.password))
+    }
+
+    fn pair(login: &str, password: &str) -> Option<(String, String)> {
+        Some((login.to_owned(), password.to_owned()))
+    }
+
+    #[test]
+    fn finds_the_machine() {
+        assert_eq!(
+            login(NETRC, "api.example.com", false),
+            pair("alice", "s3cret")
+        );
+        assert_eq!(
+            login(NETRC, "other.example.com", false),
+            pair("bob", "hunter2")
+        );
+    }
+
+    #[test]
+    fn uses_the_default_only_when_asked() {
+        assert_eq!(login(NETRC, "unknown.example.com", false), None);
+        assert_eq!(
+            login(NETRC, "unknown.example.com", true),
+            pair("anonymous", "guest")
+        );
+        assert_eq!(
+            login(NETRC, "api.example.com", true),
+            pair("alice", "s3cret")
+        );
+    }
+
+    #[test]
+    fn skips_macros() {
+        let content = "macdef init\nmachine evil.example.com login m password p\n\n\
+                       machine good.example.com login g password q\n";
+        assert_eq!(login(content, "evil.example.com", false), None);
+        assert_eq!(login(content, "good.example.com", false), pair("g", "q"));
+    }
+
+    #[test]
+    fn reads_netrc_only_when_asked() {
+        use structopt::StructOpt;
+
+        let path = std::env::temp_dir().join(format!("hurl-netrc-{}", std::process::id()));
+        fs::write(&path, "machine example.com login alice password s3cret\n").unwrap();
+        std::env::set_var("NETRC", &path);
+        let url = Url::parse("http://example.com/").unwrap();
+        let resolve_with = |args: &[&str]| {
+            let app = App::from_iter(args.iter().chain(&["http://example.com/"]));
+            resolve(&app, &url).unwrap()
+        };
+
+        assert_eq!(resolve_with(&["hurl"]), None);
+        assert_eq!(
+            resolve_with(&["hurl", "--netrc"]),
+            Some("alice:s3cret".to_owned())
+        );
+        assert_eq!(
+            resolve_with(&["hurl", "--netrc", "--auth", "alice"]),
+            Some("alice:s3cret".to_owned())
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn needs_a_login_and_password() {
+        assert_eq!(login("machine a login x", "a", false), None);
+        assert_eq!(login("machine a password y", "a", false), None);
+        assert_eq!(login("login x password y", "a", true), None);
+        assert_eq!(login("", "a", true), None);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::errors::{Error, HurlResult};
+use serde_json::{Map, Value};
+
+/// The largest array index a data path may set, as the elements before it
+/// are filled with nulls.
+pub const MAX_INDEX: usize = 10_000;
+
+/// A single step in the key of a data parameter.
+#[derive(Debug, PartialEq)]
+pub enum Segment {
+    Field(String),
+    Index(usize),
+    Append,
+}
+
+/// Parse the key of a data parameter such as `user[name]`, `user.name`,
+/// `user[tags][]` or `items[0][id]`.
+///
+/// A `\` makes the next character part of a field name, so `a\.b` is the
+/// single field `a.b`.
+pub fn parse(key: &str) -> HurlResult<Vec<Segment>> {
+    let invalid = || Error::InvalidDataPath(key.to_owned());
+    let mut segments = Vec::new();
+    let mut chars = key.chars().peekable();
+
+    let first = field(&mut chars, &['[', '.']);
+    if first.is_empty() {
+        return Err(invalid());
+    }
+    segments.push(Segment::Field(first));
+
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => {
+                let inner = field(&mut chars, &[']']);
+                if chars.next() != Some(']') {
+                    return Err(invalid());
+                }
+                if inner.is_empty() {
+                    segments.push(Segment::Append);
+                } else if inner.bytes().all(|b| b.is_ascii_digit()) {
+                    segments.push(Segment::Index(inner.parse().map_err(|_| invalid())?));
+                } else {
+                    segments.push(Segment::Field(inner));
+                }
+            }
+            '.' => {
+                let name = field(&mut chars, &['[', '.']);
+                if name.is_empty() {
+                    return Err(invalid());
+                }
+                segments.push(Segment::Field(name));
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(segments)
+}
+
+/// Read characters up to one of `stop`, unescaping `\` sequences.
+fn field<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>, stop: &[char]) -> String {
+    let mut out = String::new();
+    while let Some(&c) = chars.peek() {
+        if stop.contains(&c) {
+            break;
+        }
+        chars.next();
+        if c == '\\' {
+            if let Some(escaped) = chars.next() {
+                out.push(escaped);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Set `value` at the path given by `key` in `root`, creating objects and
+/// arrays along the way.
+pub fn insert(root: &mut Map<String, Value>, key: &str, value: Value) -> HurlResult<()> {
+    let segments = parse(key)?;
+    let conflict = |path: &str, found: &Value, wanted: &str| {
+        Error::DataPathConflict(
+            key.to_owned(),
+            format!("{} is {}, not {}", path, describe(found), wanted),
+        )
+    };
+
+    let (first, rest) = match segments.split_first() {
+        Some((Segment::Field(name), rest)) => (name, rest),
+        _ => return Err(Error::InvalidDataPath(key.to_owned())),
+    };
+    let mut path = first.clone();
+    let mut current = root.entry(first.clone()).or_insert(Value::Null);
+
+    for segment in rest.iter() {
+        if current.is_null() {
+            *current = match segment {
+                Segment::Field(_) => Value::Object(Map::new()),
+                _ => Value::Array(Vec::new()),
+            };
+        }
+        current = match segment {
+            Segment::Field(name) => {
+                let object = match current {
+                    Value::Object(object) => object,
+                    other => return Err(conflict(&path, other, "an object")),
+                };
+                path = format!("{}[{}]", path, name);
+                object.entry(name.clone()).or_insert(Value::Null)
+            }
+            Segment::Index(idx) => {
+                let array = match current {
+                    Value::Array(array) => array,
+                    other => return Err(conflict(&path, other, "an array")),
+                };
+                if *idx > MAX_INDEX {
+                    return Err(Error::DataPathIndex(key.to_owned(), *idx));
+                }
+                path = format!("{}[{}]", path, idx);
+                if *idx >= array.len() {
+                    array.resize(*idx + 1, Value::Null);
+                }
+                &mut array[*idx]
+            }
+            Segment::Append => {
+                let array = match current {
+                    Value::Array(array) => array,
+                    other => return Err(conflict(&path, other, "an array")),
+                };
+                path = format!("{}[{}]", path, array.len());
+                array.push(Value::Null);
+                array.last_mut().unwrap()
+            }
+        };
+    }
+
+    let is_container = match current {
+        Value::Object(object) => !object.is_empty(),
+        Value::Array(array) => !array.is_empty(),
+        _ => false,
+    };
+    if is_container {
+        return Err(conflict(&path, current, "a single value"));
+    }
+    *current = value;
+    Ok(())
+}
+
+fn describe(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Flatten nested data into `key[field]` and `key[]` pairs for a form body.
+pub fn flatten(data: &Map<String, Value>) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    for (key, value) in data.iter() {
+        flatten_into(key.clone(), value, &mut pairs);
+    }
+    pairs
+}
+
+fn flatten_into(prefix: String, value: &Value, pairs: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter() {
+                flatten_into(format!("{}[{}]", prefix, key), value, pairs);
+            }
+        }
+        Value::Array(array) => {
+            let nested = array
+                .iter()
+                .any(|v| matches!(v, Value::Object(_) | Value::Array(_)));
+            for (i, value) in array.iter().enumerate() {
+                if nested {
+                    flatten_into(format!("{}[{}]", prefix, i), value, pairs);
+                } else {
+                    flatten_into(format!("{}[]", prefix), value, pairs);
+                }
+            }
+        }
+        Value::Null => pairs.push((prefix, String::new())),
+        Value::String(s) => pairs.push((prefix, s.clone())),
+        other => pairs.push((prefix, other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn build(pairs: &[(&str, Value)]) -> HurlResult<Value> {
+        let mut root = Map::new();
+        for (key, value) in pairs.iter() {
+            insert(&mut root, key, value.clone())?;
+        }
+        Ok(Value::Object(root))
+    }
+
+    #[test]
+    fn parses_brackets_dots_and_escapes() {
+        assert_eq!(
+            parse("items[0][id]").unwrap(),
+            vec![
+                Segment::Field("items".to_owned()),
+                Segment::Index(0),
+                Segment::Field("id".to_owned()),
+            ]
+        );
+        assert_eq!(
+            parse("user.tags[]").unwrap(),
+            vec![
+                Segment::Field("user".to_owned()),
+                Segment::Field("tags".to_owned()),
+                Segment::Append,
+            ]
+        );
+        assert_eq!(
+            parse(r"a\.b").unwrap(),
+            vec![Segment::Field("a.b".to_owned())]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_keys() {
+        for key in &["", "[0]", "a[", "a.", "a..b", "a[0]x"] {
+            assert!(parse(key).is_err(), "{} should not parse", key);
+        }
+    }
+
+    #[test]
+    fn builds_nested_values() {
+        let value = build(&[
+            ("user[name]", json!("x")),
+            ("user.tags[]", json!("a")),
+            ("user.tags[]", json!("b")),
+            ("items[1][id]", json!(3)),
+        ])
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "user": {"name": "x", "tags": ["a", "b"]},
+                "items": [null, {"id": 3}],
+            })
+        );
+    }
+
+    #[test]
+    fn reports_conflicting_types() {
+        let err = build(&[("a", json!(1)), ("a[b]", json!(2))]).unwrap_err();
+        assert!(matches!(err, Error::DataPathConflict(..)));
+    }
+
+    #[test]
+    fn caps_array_indices() {
+        assert!(build(&[("a[10000]", json!(1))]).is_ok());
+        let err = build(&[("a[10001]", json!(1))]).unwrap_err();
+        assert!(matches!(err, Error::DataPathIndex(_, 10_001)));
+    }
+
+    #[test]
+    fn flattens_for_forms() {
+        let value = build(&[
+            ("user[name]", json!("x")),
+            ("tags[]", json!("a")),
+            ("tags[]", json!("b")),
+        ])
+        .unwrap();
+        let pairs = flatten(value.as_object().unwrap());
+        assert_eq!(
+            pairs,
+            vec![
+                ("tags[]".to_owned(), "a".to_owned()),
+                ("tags[]".to_owned(), "b".to_owned()),
+                ("user[name]".to_owned(), "x".to_owned()),
+            ]
+        );
+    }
+}
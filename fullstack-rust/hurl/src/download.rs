@@ -0,0 +1,284 @@
+use crate::app::App;
+use crate::errors::{Error, HurlResult};
+use log::{debug, trace};
+use reqwest::header::{CONTENT_DISPOSITION, CONTENT_RANGE};
+use reqwest::{Response, StatusCode};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The offset to resume an interrupted download from, if --continue was
+/// given and part of the output file already exists.
+pub fn resume_offset(app: &App) -> HurlResult<Option<u64>> {
+    if !app.resume {
+        return Ok(None);
+    }
+    let path = app.output.as_ref().ok_or(Error::ResumeWithoutOutput)?;
+    match fs::metadata(path) {
+        Ok(meta) if meta.len() > 0 => Ok(Some(meta.len())),
+        Ok(_) => Ok(None),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Stream the body of `resp` to a file, returning the path written to.
+///
+/// With --continue a 206 response is added to the file and a 200 response
+/// replaces it, while any other status leaves it as it is.
+pub fn save(app: &App, resp: &mut Response) -> HurlResult<PathBuf> {
+    let offset = resume_offset(app)?;
+    let path = match &app.output {
+        Some(path) => path.clone(),
+        None => unique_path(Path::new(&filename_from_response(resp))),
+    };
+
+    if resp.status() == StatusCode::RANGE_NOT_SATISFIABLE && offset.is_some() {
+        // The range is past the end, which means the file is complete unless
+        // the server says it is of some other length.
+        let length = resp
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("bytes */"))
+            .and_then(|v| v.parse::<u64>().ok());
+        if length.is_some() && length != offset {
+            return Err(Error::ResumeFailed(path, resp.status()));
+        }
+        eprintln!("{} is already complete", path.display());
+        return Ok(path);
+    }
+    // Only a full response may replace the part already downloaded.
+    if offset.is_some()
+        && resp.status() != StatusCode::PARTIAL_CONTENT
+        && resp.status() != StatusCode::OK
+    {
+        return Err(Error::ResumeFailed(path, resp.status()));
+    }
+    let resuming = offset.is_some() && resp.status() == StatusCode::PARTIAL_CONTENT;
+    let file = if resuming {
+        debug!("Resuming download of {} at {:?}", path.display(), offset);
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(|e| Error::FileWrite(path.clone(), e))?
+    } else {
+        if offset.is_some() {
+            debug!("Server ignored the Range request, starting again");
+        }
+        File::create(&path).map_err(|e| Error::FileWrite(path.clone(), e))?
+    };
+
+    let start = if resuming { offset.unwrap_or(0) } else { 0 };
+    let total = resp
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit('/').next())
+        .and_then(|v| v.parse::<u64>().ok())
+        .or_else(|| resp.content_length().map(|len| len + start));
+
+    let mut progress = Progress::new(total, start, atty::is(atty::Stream::Stderr) && !app.quiet);
+    copy_with_progress(resp, file, &mut progress)?;
+    progress.finish(&path);
+    Ok(path)
+}
+
+fn copy_with_progress<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    progress: &mut Progress,
+) -> HurlResult<()> {
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        writer.write_all(&buf[..n])?;
+        progress.advance(n as u64);
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Pick a file name from the Content-Disposition header, falling back to
+/// the last segment of the URL path.
+fn filename_from_response(resp: &Response) -> String {
+    let from_header = resp
+        .headers()
+        .get(CONTENT_DISPOSITION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(content_disposition_filename);
+    let from_url = resp
+        .url()
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_owned());
+    let name = from_header
+        .or(from_url)
+        .map(|name| sanitize(&name))
+        .filter(|name| !name.is_empty() && name != "." && name != "..")
+        .unwrap_or_else(|| "download".to_owned());
+    trace!("Download file name: {}", name);
+    name
+}
+
+fn content_disposition_filename(value: &str) -> Option<String> {
+    let mut plain = None;
+    for part in value.split(';').map(str::trim) {
+        let mut kv = part.splitn(2, '=');
+        let key = kv.next().unwrap_or("").trim().to_ascii_lowercase();
+        let val = kv.next().unwrap_or("").trim();
+        if key == "filename*" {
+            // RFC 5987: charset'language'percent-encoded-value
+            if let Some(encoded) = val.splitn(3, '\'').nth(2) {
+                return Some(percent_decode(encoded));
+            }
+        } else if key == "filename" {
+            plain = Some(val.trim_matches('"').to_owned());
+        }
+    }
+    plain
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Keep only the final path component so a server cannot choose where the
+/// file is written.
+fn sanitize(name: &str) -> String {
+    name.rsplit(&['/', '\\'][..])
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_owned()
+}
+
+/// Add `-1`, `-2`, ... to the file stem until the path does not exist.
+fn unique_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_owned();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|i| path.with_file_name(format!("{}-{}{}", stem, i, extension)))
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
+struct Progress {
+    total: Option<u64>,
+    done: u64,
+    resumed_at: u64,
+    started: Instant,
+    drawn: Option<Instant>,
+    enabled: bool,
+}
+
+impl Progress {
+    fn new(total: Option<u64>, resumed_at: u64, enabled: bool) -> Self {
+        Progress {
+            total,
+            done: resumed_at,
+            resumed_at,
+            started: Instant::now(),
+            drawn: None,
+            enabled,
+        }
+    }
+
+    fn advance(&mut self, n: u64) {
+        self.done += n;
+        let due = self
+            .drawn
+            .map(|t| t.elapsed() >= Duration::from_millis(100))
+            .unwrap_or(true);
+        if self.enabled && due {
+            self.draw();
+            self.drawn = Some(Instant::now());
+        }
+    }
+
+    fn rate(&self) -> f64 {
+        let secs = self.started.elapsed().as_secs_f64().max(0.001);
+        (self.done - self.resumed_at) as f64 / secs
+    }
+
+    fn draw(&self) {
+        let line = match self.total {
+            Some(total) if total > 0 => {
+                let fraction = (self.done as f64 / total as f64).min(1.0);
+                let width = 30;
+                let filled = (fraction * width as f64) as usize;
+                format!(
+                    "[{}{}] {:>3}% {} / {} {}/s",
+                    "#".repeat(filled),
+                    " ".repeat(width - filled),
+                    (fraction * 100.0) as u32,
+                    human_bytes(self.done),
+                    human_bytes(total),
+                    human_bytes(self.rate() as u64)
+                )
+            }
+            _ => format!(
+                "{} {}/s",
+                human_bytes(self.done),
+                human_bytes(self.rate() as u64)
+            ),
+        };
+        eprint!("\r{:<72}", line);
+    }
+
+    fn finish(&self, path: &Path) {
+        if self.enabled {
+            self.draw();
+            eprintln!();
+        }
+        eprintln!(
+            "Downloaded {} in {:.2}s to {}",
+            human_bytes(self.done - self.resumed_at),
+            self.started.elapsed().as_secs_f64(),
+            path.display()
+        );
+    }
+}
+
+pub fn human_bytes(n: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = n as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", n, units[0])
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
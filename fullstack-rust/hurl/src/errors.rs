@@ -1,17 +1,72 @@
+use crate::auth::AuthType;
 use std::fmt;
 
 pub enum Error {
     ParameterMissingSeparator(String),
     MissingUrlAndCommand,
     NotFormButHasFormFile,
-    ClientSerialization,
-    ClientTimeout,
+    Client(reqwest::Error),
     ClientWithStatus(reqwest::StatusCode),
-    ClientOther,
-    SerdeJson(serde_json::error::Category),
-    IO(std::io::ErrorKind),
-    UrlParseError(reqwest::UrlError),
+    FormEncoding(serde_urlencoded::ser::Error),
+    SerdeJson(serde_json::Error),
+    IO(std::io::Error),
+    File(std::path::PathBuf, std::io::Error),
+    FileWrite(std::path::PathBuf, std::io::Error),
+    Parameter(String, Box<Error>),
+    UrlParseError(String, reqwest::UrlError),
     SyntaxLoadError(&'static str),
+    RequestFileSyntax(usize, String),
+    UndefinedVariable(String),
+    InvalidMethod(String),
+    InvalidJsonPath(String),
+    CaptureFailed(String),
+    InvalidAssertion(String),
+    AssertionsFailed(usize),
+    SessionNotFound(String, String),
+    SessionExists(String, String),
+    SecretEncryption,
+    SecretDecryption,
+    SecretLocked,
+    UnknownProfile(String),
+    InvalidHeader(String),
+    Config(std::path::PathBuf, Option<(usize, usize)>, String),
+    ConfigNotFound(std::path::PathBuf),
+    ResumeWithoutOutput,
+    ResumeFailed(std::path::PathBuf, reqwest::StatusCode),
+    InvalidPrint(String),
+    InvalidDataPath(String),
+    DataPathConflict(String, String),
+    DataPathIndex(String, usize),
+    RawBodyWithData,
+    InvalidTimeout(String),
+    InvalidProxy(String),
+    Certificate(std::path::PathBuf, String),
+    InvalidExport(String),
+    InvalidOutputFormat(String),
+    InvalidColor(String),
+    JsonOutputWith(&'static str),
+    InvalidCurl(String),
+    InvalidHar(std::path::PathBuf, serde_json::Error),
+    UnknownHarEntry(usize, usize),
+    UnsupportedImport(String),
+    HistoryEntryNotFound(u64),
+    InvalidHistoryEntry(u64),
+    InvalidCount(String),
+    RepeatStreamingBody,
+    OAuth(String),
+    OAuthPasswordMissing,
+    InvalidOAuthGrant(String),
+    InvalidAuthType(String),
+    MissingCredentials(AuthType),
+    UnsupportedDigest(String),
+    MissingAwsScope(String),
+    UnsignedPayload(String),
+    PasswordRequired(String),
+    NoTerminal(&'static str),
+    AuthCommand(String, String),
+    InvalidFilter(String, String),
+    FilterType(String, &'static str),
+    FilterNotJson,
 }
 
 pub type HurlResult<T> = Result<T, Error>;
@@ -27,14 +82,192 @@ impl fmt::Display for Error {
                 f,
                 "Cannot have a form file 'key@filename' unless --form option is set"
             ),
-            Error::ClientSerialization => write!(f, "Serializing the request/response failed"),
-            Error::ClientTimeout => write!(f, "Timeout during request"),
+            Error::Client(e) if e.is_timeout() => write!(f, "Timeout during request"),
+            Error::Client(e) if e.is_redirect() => write!(
+                f,
+                "Too many redirects, use --max-redirects to allow more"
+            ),
+            Error::Client(e) => match e.url() {
+                Some(url) => write!(f, "Request to {} failed", url),
+                None => write!(f, "Request failed"),
+            },
             Error::ClientWithStatus(status) => write!(f, "Got status code: {}", status),
-            Error::ClientOther => write!(f, "Unknown client error"),
-            Error::SerdeJson(c) => write!(f, "JSON error: {:?}", c),
-            Error::IO(k) => write!(f, "IO Error: {:?}", k),
-            Error::UrlParseError(e) => write!(f, "URL Parsing Error: {}", e),
+            Error::FormEncoding(_) => write!(f, "Could not encode the form body"),
+            Error::SerdeJson(e) => write!(f, "Invalid JSON: {}", e),
+            Error::IO(e) => write!(f, "{}", e),
+            Error::File(path, _) => write!(f, "Could not open {}", path.display()),
+            Error::FileWrite(path, _) => write!(f, "Could not write {}", path.display()),
+            Error::Parameter(param, _) => write!(f, "Invalid parameter `{}`", param),
+            Error::UrlParseError(url, _) => write!(f, "Invalid URL: {}", url),
             Error::SyntaxLoadError(typ) => write!(f, "Error loading syntax for {}", typ),
+            Error::RequestFileSyntax(line, msg) => {
+                write!(f, "Error in request file on line {}: {}", line, msg)
+            }
+            Error::UndefinedVariable(name) => write!(f, "Undefined variable: {{{{{}}}}}", name),
+            Error::InvalidMethod(m) => write!(f, "Invalid HTTP method: {}", m),
+            Error::InvalidJsonPath(p) => write!(f, "Invalid JSON path: {}", p),
+            Error::CaptureFailed(name) => write!(f, "Could not capture a value for: {}", name),
+            Error::InvalidAssertion(a) => write!(f, "Invalid assertion: {}", a),
+            Error::AssertionsFailed(n) => write!(f, "{} assertion(s) failed", n),
+            Error::SessionNotFound(host, name) => {
+                write!(f, "No session named {} for host {}", name, host)
+            }
+            Error::SessionExists(host, name) => write!(
+                f,
+                "A session named {} already exists for host {}, use --force to replace it",
+                name, host
+            ),
+            Error::UnknownProfile(name) => write!(f, "No profile named {} in config", name),
+            Error::InvalidHeader(h) => write!(f, "Invalid header: {}", h),
+            Error::Config(path, Some((line, col)), msg) => write!(
+                f,
+                "Invalid config file {} at line {}, column {}: {}",
+                path.display(),
+                line,
+                col,
+                msg
+            ),
+            Error::Config(path, None, msg) => {
+                write!(f, "Invalid config file {}: {}", path.display(), msg)
+            }
+            Error::ConfigNotFound(path) => {
+                write!(f, "Config file {} does not exist", path.display())
+            }
+            Error::InvalidTimeout(s) => write!(f, "Invalid timeout: {}, expected seconds", s),
+            Error::InvalidExport(s) => write!(
+                f,
+                "Invalid --export format {}, expected one of curl, httpie, rust-reqwest, python-requests",
+                s
+            ),
+            Error::InvalidOutputFormat(s) => {
+                write!(f, "Invalid --output-format {}, expected one of text, json", s)
+            }
+            Error::JsonOutputWith(option) => {
+                write!(f, "--output-format json cannot be used with {}", option)
+            }
+            Error::InvalidColor(s) => {
+                write!(f, "Invalid --color {}, expected one of always, never, auto", s)
+            }
+            Error::InvalidProxy(p) => write!(f, "Invalid proxy: {}", p),
+            Error::InvalidCurl(msg) => write!(f, "Invalid curl command: {}", msg),
+            Error::InvalidHar(path, _) => write!(f, "Invalid HAR file {}", path.display()),
+            Error::UnknownHarEntry(position, count) => write!(
+                f,
+                "There is no entry {} in the HAR file, which has {} entries",
+                position, count
+            ),
+            Error::UnsupportedImport(msg) => write!(f, "Cannot import request: {}", msg),
+            Error::HistoryEntryNotFound(id) => write!(f, "No request {} in the history", id),
+            Error::InvalidHistoryEntry(id) => write!(f, "Request {} in the history is damaged", id),
+            Error::InvalidCount(s) => write!(f, "Invalid count {}, expected a number above 0", s),
+            Error::RepeatStreamingBody => write!(f, "A multipart request cannot be repeated"),
+            Error::OAuth(reason) => write!(f, "Could not get an OAuth2 token: {}", reason),
+            Error::OAuthPasswordMissing => write!(
+                f,
+                "The OAuth2 password grant needs --auth username:password"
+            ),
+            Error::InvalidOAuthGrant(s) => write!(
+                f,
+                "Invalid OAuth2 grant {}, expected client-credentials or password",
+                s
+            ),
+            Error::InvalidAuthType(s) => write!(
+                f,
+                "Invalid --auth-type {}, expected one of basic, bearer, digest, apikey, sigv4",
+                s
+            ),
+            Error::MissingCredentials(AuthType::Bearer) => {
+                write!(f, "Bearer authentication needs --token")
+            }
+            Error::MissingCredentials(AuthType::SigV4) => write!(
+                f,
+                "sigv4 authentication needs --auth access-key-id:secret-access-key"
+            ),
+            Error::MissingCredentials(auth_type) => {
+                write!(f, "{} authentication needs --auth", auth_type)
+            }
+            Error::UnsupportedDigest(s) => {
+                write!(f, "Unsupported Digest authentication challenge: {}", s)
+            }
+            Error::MissingAwsScope(host) => write!(
+                f,
+                "Cannot tell the AWS region and service from host {}, use --aws-region and --aws-service",
+                host
+            ),
+            Error::UnsignedPayload(service) => write!(
+                f,
+                "Cannot sign a multipart body for {}, only s3 accepts an unsigned payload",
+                service
+            ),
+            Error::PasswordRequired(username) => write!(
+                f,
+                "No password for {} and no terminal to ask for one, give it with --auth, HURL_AUTH, ~/.netrc or auth_command",
+                username
+            ),
+            Error::NoTerminal(what) => {
+                write!(f, "No terminal to ask for the {} on", what)
+            }
+            Error::AuthCommand(command, reason) => {
+                write!(f, "auth_command `{}` failed: {}", command, reason)
+            }
+            Error::InvalidFilter(filter, reason) => {
+                write!(f, "Invalid filter {}: {}", filter, reason)
+            }
+            Error::FilterType(step, value) => write!(f, "Cannot take {} of {}", step, value),
+            Error::FilterNotJson => write!(f, "Cannot filter a response body which is not JSON"),
+            Error::Certificate(path, msg) => {
+                write!(f, "Could not load certificate {}: {}", path.display(), msg)
+            }
+            Error::RawBodyWithData => write!(
+                f,
+                "A raw body (from --raw or stdin) cannot be mixed with data parameters"
+            ),
+            Error::InvalidDataPath(key) => write!(f, "Invalid data path: {}", key),
+            Error::DataPathConflict(key, reason) => {
+                write!(f, "Conflicting data for {}: {}", key, reason)
+            }
+            Error::DataPathIndex(key, idx) => write!(
+                f,
+                "Index {} in data path {} is too large, the most is {}",
+                idx,
+                key,
+                crate::datapath::MAX_INDEX
+            ),
+            Error::InvalidPrint(s) => {
+                write!(f, "Invalid --print value {}, expected letters from HBhb", s)
+            }
+            Error::ResumeWithoutOutput => write!(f, "--continue requires --output"),
+            Error::ResumeFailed(path, status) => write!(
+                f,
+                "Could not resume {}, got status code: {}",
+                path.display(),
+                status
+            ),
+            Error::SecretEncryption => write!(f, "Failed to encrypt secret"),
+            Error::SecretDecryption => write!(
+                f,
+                "Failed to decrypt secret, the key or passphrase may be wrong"
+            ),
+            Error::SecretLocked => write!(
+                f,
+                "A key is needed for encrypted secrets, use --key-file or --encrypt-secrets"
+            ),
+        }
+    }
+}
+
+impl Error {
+    /// The process exit code to use when hurl stops because of this error.
+    ///
+    /// Transport failures exit with 2, responses with an error status (when
+    /// --check-status is set) with 3, failed assertions with 4, and
+    /// everything else with 1.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Client(_) => 2,
+            Error::ClientWithStatus(_) | Error::ResumeFailed(..) => 3,
+            Error::AssertionsFailed(_) => 4,
+            _ => 1,
         }
     }
 }
@@ -48,7 +281,14 @@ impl fmt::Debug for Error {
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            Error::UrlParseError(e) => Some(e),
+            Error::Client(e) => Some(e),
+            Error::FormEncoding(e) => Some(e),
+            Error::File(_, e) | Error::FileWrite(_, e) => Some(e),
+            Error::Parameter(_, e) => Some(e.as_ref()),
+            Error::UrlParseError(_, e) => Some(e),
+            Error::InvalidHar(_, e) => Some(e),
+            Error::IO(e) => e.source(),
+            Error::SerdeJson(e) => e.source(),
             _ => None,
         }
     }
@@ -57,36 +297,38 @@ impl std::error::Error for Error {
 impl From<reqwest::Error> for Error {
     #[inline]
     fn from(err: reqwest::Error) -> Error {
-        if err.is_serialization() {
-            return Error::ClientSerialization;
-        }
-        if err.is_timeout() {
-            return Error::ClientTimeout;
-        }
-        if let Some(s) = err.status() {
-            return Error::ClientWithStatus(s);
-        }
-        Error::ClientOther
+        Error::Client(err)
     }
 }
 
 impl From<serde_json::error::Error> for Error {
     #[inline]
     fn from(err: serde_json::error::Error) -> Error {
-        Error::SerdeJson(err.classify())
+        Error::SerdeJson(err)
     }
 }
 
 impl From<std::io::Error> for Error {
     #[inline]
     fn from(err: std::io::Error) -> Error {
-        Error::IO(err.kind())
+        Error::IO(err)
     }
 }
 
-impl From<reqwest::UrlError> for Error {
-    #[inline]
-    fn from(err: reqwest::UrlError) -> Error {
-        Error::UrlParseError(err)
+/// Print an error to stderr followed by each error which caused it.
+///
+/// A cause is skipped when the message before it already includes it, as
+/// many errors repeat the message of their source.
+pub fn report(err: &Error) {
+    eprintln!("Error: {}", err);
+    let mut previous = err.to_string();
+    let mut source = std::error::Error::source(err);
+    while let Some(cause) = source {
+        let message = cause.to_string();
+        if !previous.contains(&message) {
+            eprintln!("  Caused by: {}", message);
+        }
+        previous = message;
+        source = cause.source();
     }
 }
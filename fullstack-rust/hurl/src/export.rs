@@ -0,0 +1,556 @@
+use crate::app::App;
+use crate::client::{Part, Prepared, DEFAULT_MAX_REDIRECTS};
+use crate::errors::{Error, HurlResult};
+use crate::tls::is_pkcs12;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Method;
+use std::path::Path;
+
+/// The formats accepted by --export.
+pub const FORMATS: &[&str] = &["curl", "httpie", "rust-reqwest", "python-requests"];
+
+/// What to export a request as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Curl,
+    Httpie,
+    RustReqwest,
+    PythonRequests,
+}
+
+pub fn parse_format(src: &str) -> HurlResult<Format> {
+    match src {
+        "curl" => Ok(Format::Curl),
+        "httpie" => Ok(Format::Httpie),
+        "rust-reqwest" => Ok(Format::RustReqwest),
+        "python-requests" => Ok(Format::PythonRequests),
+        _ => Err(Error::InvalidExport(src.to_owned())),
+    }
+}
+
+/// The resolved parts of a request which are written out.
+struct Request<'a> {
+    method: &'a Method,
+    url: &'a str,
+    headers: Vec<(String, String)>,
+    body: Option<&'a [u8]>,
+    parts: &'a [Part],
+}
+
+impl<'a> Request<'a> {
+    fn new(app: &App, prepared: &'a Prepared) -> Self {
+        let request = &prepared.request;
+        let mut headers = Vec::new();
+        for (key, value) in app.default_headers.iter() {
+            if !request.headers().contains_key(key.as_str()) {
+                headers.push((crate::header_name(key), value.clone()));
+            }
+        }
+        for (key, value) in request.headers().iter() {
+            // A multipart Content-Type holds a boundary which the exported
+            // command or snippet makes up for itself.
+            if key == CONTENT_TYPE && !prepared.parts.is_empty() {
+                continue;
+            }
+            headers.push((
+                crate::header_name(key.as_str()),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            ));
+        }
+        Request {
+            method: request.method(),
+            url: request.url().as_str(),
+            headers,
+            body: prepared.body.as_deref(),
+            parts: &prepared.parts,
+        }
+    }
+
+    fn has_body(&self) -> bool {
+        self.body.is_some() || !self.parts.is_empty()
+    }
+}
+
+/// Write a prepared request as a command or code snippet which sends the
+/// same request.
+pub fn render(app: &App, format: Format, prepared: &Prepared) -> String {
+    let request = Request::new(app, prepared);
+    match format {
+        Format::Curl => curl(app, &request),
+        Format::Httpie => httpie(app, &request),
+        Format::RustReqwest => rust_reqwest(app, &request),
+        Format::PythonRequests => python_requests(app, &request),
+    }
+}
+
+fn curl(app: &App, request: &Request) -> String {
+    let mut args = vec!["curl".to_owned()];
+    let default = if request.has_body() {
+        Method::POST
+    } else {
+        Method::GET
+    };
+    if *request.method == Method::HEAD && !request.has_body() {
+        args.push("--head".to_owned());
+    } else if *request.method != default {
+        args.push(format!("-X {}", request.method));
+    }
+    args.push(shell_quote(request.url));
+    for (key, value) in request.headers.iter() {
+        args.push(format!("-H {}", shell_quote(&format!("{}: {}", key, value))));
+    }
+    if let Some(body) = request.body {
+        // --data-binary would read a body starting with @ from a file.
+        let flag = if body.starts_with(b"@") {
+            "--data-raw"
+        } else {
+            "--data-binary"
+        };
+        args.push(format!("{} {}", flag, shell_quote_bytes(body)));
+    }
+    for part in request.parts.iter() {
+        match part {
+            Part::Text(key, value) => {
+                args.push(format!("--form-string {}", shell_quote(&format!("{}={}", key, value))))
+            }
+            Part::File(key, file) => {
+                args.push(format!("-F {}", shell_quote(&format!("{}=@{}", key, file))))
+            }
+        }
+    }
+
+    if app.follow {
+        args.push("-L".to_owned());
+        if let Some(max) = app.max_redirects {
+            args.push(format!("--max-redirs {}", max));
+        }
+    }
+    if let Some(timeout) = app.timeout {
+        args.push(format!("--max-time {}", timeout.as_secs_f64()));
+    }
+    if let Some(timeout) = app.connect_timeout {
+        args.push(format!("--connect-timeout {}", timeout.as_secs_f64()));
+    }
+    if let Some(proxy) = &app.proxy {
+        args.push(format!("-x {}", shell_quote(proxy)));
+    }
+    if app.insecure {
+        args.push("-k".to_owned());
+    }
+    if let Some(path) = &app.cacert {
+        args.push(format!("--cacert {}", shell_quote_path(path)));
+    }
+    if let Some(path) = &app.cert {
+        if is_pkcs12(path) {
+            args.push("--cert-type P12".to_owned());
+        }
+        args.push(format!("--cert {}", shell_quote_path(path)));
+    }
+    if let Some(path) = &app.key {
+        args.push(format!("--key {}", shell_quote_path(path)));
+    }
+    args.join(" \\\n  ")
+}
+
+fn httpie(app: &App, request: &Request) -> String {
+    let mut args = vec!["http".to_owned()];
+    if !request.parts.is_empty() {
+        args.push("--multipart".to_owned());
+    }
+    if app.follow {
+        args.push("--follow".to_owned());
+        if let Some(max) = app.max_redirects {
+            args.push(format!("--max-redirects={}", max));
+        }
+    }
+    if let Some(timeout) = app.timeout.or(app.connect_timeout) {
+        args.push(format!("--timeout={}", timeout.as_secs_f64()));
+    }
+    if let Some(proxy) = &app.proxy {
+        args.push(shell_quote(&format!("--proxy=http:{}", proxy)));
+        args.push(shell_quote(&format!("--proxy=https:{}", proxy)));
+    }
+    if app.insecure {
+        args.push("--verify=no".to_owned());
+    } else if let Some(path) = &app.cacert {
+        args.push(shell_quote(&format!("--verify={}", path.display())));
+    }
+    if let Some(path) = &app.cert {
+        args.push(shell_quote(&format!("--cert={}", path.display())));
+    }
+    if let Some(path) = &app.key {
+        args.push(shell_quote(&format!("--cert-key={}", path.display())));
+    }
+    if let Some(body) = request.body {
+        args.push(format!("--raw {}", shell_quote_bytes(body)));
+    }
+
+    args.push(request.method.to_string());
+    args.push(shell_quote(request.url));
+    for (key, value) in request.headers.iter() {
+        if value.is_empty() {
+            args.push(shell_quote(&format!("{};", key)));
+        } else {
+            args.push(shell_quote(&format!("{}:{}", key, value)));
+        }
+    }
+    for part in request.parts.iter() {
+        match part {
+            Part::Text(key, value) => args.push(shell_quote(&format!("{}={}", key, value))),
+            Part::File(key, file) => args.push(shell_quote(&format!("{}@{}", key, file))),
+        }
+    }
+    args.join(" \\\n  ")
+}
+
+fn rust_reqwest(app: &App, request: &Request) -> String {
+    let mut client = Vec::new();
+    if app.follow {
+        let max = app.max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS);
+        client.push(format!(".redirect(reqwest::redirect::Policy::limited({}))", max));
+    } else {
+        client.push(".redirect(reqwest::redirect::Policy::none())".to_owned());
+    }
+    if let Some(timeout) = app.timeout {
+        client.push(format!(
+            ".timeout(std::time::Duration::from_secs_f64({:?}))",
+            timeout.as_secs_f64()
+        ));
+    }
+    if let Some(timeout) = app.connect_timeout {
+        client.push(format!(
+            ".connect_timeout(std::time::Duration::from_secs_f64({:?}))",
+            timeout.as_secs_f64()
+        ));
+    }
+    if let Some(proxy) = &app.proxy {
+        client.push(format!(".proxy(reqwest::Proxy::all({:?})?)", proxy));
+    }
+    if app.insecure {
+        client.push(".danger_accept_invalid_certs(true)".to_owned());
+    }
+    if let Some(path) = &app.cacert {
+        client.push(format!(
+            ".add_root_certificate(reqwest::Certificate::from_pem(&std::fs::read({:?})?)?)",
+            path.display().to_string()
+        ));
+    }
+    if let Some(path) = &app.cert {
+        let cert = path.display().to_string();
+        let identity = match &app.key {
+            _ if is_pkcs12(path) => format!(
+                "reqwest::Identity::from_pkcs12_der(&std::fs::read({:?})?, \"\")?",
+                cert
+            ),
+            Some(key) => format!(
+                "reqwest::Identity::from_pem(&[std::fs::read({:?})?, std::fs::read({:?})?].concat())?",
+                cert,
+                key.display().to_string()
+            ),
+            None => format!("reqwest::Identity::from_pem(&std::fs::read({:?})?)?", cert),
+        };
+        client.push(format!(".identity({})", identity));
+    }
+
+    let method = match *request.method {
+        Method::GET
+        | Method::POST
+        | Method::PUT
+        | Method::DELETE
+        | Method::HEAD
+        | Method::OPTIONS
+        | Method::CONNECT
+        | Method::PATCH
+        | Method::TRACE => format!("reqwest::Method::{}", request.method),
+        _ => format!("reqwest::Method::from_bytes(b{:?})?", request.method.as_str()),
+    };
+    let mut builder = vec![format!(".request({}, {:?})", method, request.url)];
+    for (key, value) in request.headers.iter() {
+        builder.push(format!(".header({:?}, {:?})", key, value));
+    }
+    if let Some(body) = request.body {
+        match std::str::from_utf8(body) {
+            Ok(text) => builder.push(format!(".body({:?})", text)),
+            Err(_) => builder.push(format!(".body(&b\"{}\"[..])", escape_bytes(body))),
+        }
+    }
+
+    let mut out = String::from("use reqwest::blocking::Client;\n\n");
+    out.push_str("fn main() -> Result<(), Box<dyn std::error::Error>> {\n");
+    out.push_str("    let client = Client::builder()\n");
+    for line in client.iter() {
+        out.push_str(&format!("        {}\n", line));
+    }
+    out.push_str("        .build()?;\n");
+    if !request.parts.is_empty() {
+        out.push_str("    let form = reqwest::blocking::multipart::Form::new()");
+        for part in request.parts.iter() {
+            match part {
+                Part::Text(key, value) => {
+                    out.push_str(&format!("\n        .text({:?}, {:?})", key, value))
+                }
+                Part::File(key, file) => {
+                    out.push_str(&format!("\n        .file({:?}, {:?})?", key, file))
+                }
+            }
+        }
+        out.push_str(";\n");
+        builder.push(".multipart(form)".to_owned());
+    }
+    out.push_str("    let response = client\n");
+    for line in builder.iter() {
+        out.push_str(&format!("        {}\n", line));
+    }
+    out.push_str("        .send()?;\n");
+    out.push_str("    println!(\"{}\", response.status());\n");
+    out.push_str("    println!(\"{}\", response.text()?);\n");
+    out.push_str("    Ok(())\n}");
+    out
+}
+
+fn python_requests(app: &App, request: &Request) -> String {
+    let mut out = String::from("import requests\n\n");
+    out.push_str(&format!("url = {}\n", python_str(request.url)));
+    let mut kwargs = Vec::new();
+
+    if !request.headers.is_empty() {
+        out.push_str("headers = {\n");
+        for (key, value) in request.headers.iter() {
+            out.push_str(&format!("    {}: {},\n", python_str(key), python_str(value)));
+        }
+        out.push_str("}\n");
+        kwargs.push("headers=headers".to_owned());
+    }
+    if let Some(body) = request.body {
+        match std::str::from_utf8(body) {
+            Ok(text) if text.is_ascii() => out.push_str(&format!("data = {}\n", python_str(text))),
+            // A str body is sent as Latin-1, so other text is encoded first.
+            Ok(text) => out.push_str(&format!("data = {}.encode()\n", python_str(text))),
+            Err(_) => out.push_str(&format!("data = b\"{}\"\n", escape_bytes(body))),
+        }
+        kwargs.push("data=data".to_owned());
+    }
+    if !request.parts.is_empty() {
+        let mut data = Vec::new();
+        let mut files = Vec::new();
+        for part in request.parts.iter() {
+            match part {
+                Part::Text(key, value) => {
+                    data.push(format!("    ({}, {}),\n", python_str(key), python_str(value)))
+                }
+                Part::File(key, file) => files.push(format!(
+                    "    ({}, open({}, \"rb\")),\n",
+                    python_str(key),
+                    python_str(file)
+                )),
+            }
+        }
+        if !data.is_empty() {
+            out.push_str(&format!("data = [\n{}]\n", data.concat()));
+            kwargs.push("data=data".to_owned());
+        }
+        out.push_str(&format!("files = [\n{}]\n", files.concat()));
+        kwargs.push("files=files".to_owned());
+    }
+
+    match (app.connect_timeout, app.timeout) {
+        (Some(connect), Some(read)) => kwargs.push(format!(
+            "timeout=({:?}, {:?})",
+            connect.as_secs_f64(),
+            read.as_secs_f64()
+        )),
+        (Some(connect), None) => {
+            kwargs.push(format!("timeout=({:?}, None)", connect.as_secs_f64()))
+        }
+        (None, Some(read)) => kwargs.push(format!("timeout={:?}", read.as_secs_f64())),
+        (None, None) => {}
+    }
+    if !app.follow {
+        kwargs.push("allow_redirects=False".to_owned());
+    }
+    if let Some(proxy) = &app.proxy {
+        let proxy = python_str(proxy);
+        kwargs.push(format!(
+            "proxies={{\"http\": {}, \"https\": {}}}",
+            proxy, proxy
+        ));
+    }
+    if app.insecure {
+        kwargs.push("verify=False".to_owned());
+    } else if let Some(path) = &app.cacert {
+        kwargs.push(format!("verify={}", python_str(&path.display().to_string())));
+    }
+    if let Some(path) = &app.cert {
+        let cert = python_str(&path.display().to_string());
+        match &app.key {
+            Some(key) => kwargs.push(format!(
+                "cert=({}, {})",
+                cert,
+                python_str(&key.display().to_string())
+            )),
+            None => kwargs.push(format!("cert={}", cert)),
+        }
+    }
+
+    let call = match (app.follow, app.max_redirects) {
+        (true, Some(max)) => {
+            out.push_str(&format!(
+                "\nsession = requests.Session()\nsession.max_redirects = {}\n",
+                max
+            ));
+            "session.request"
+        }
+        _ => {
+            out.push('\n');
+            "requests.request"
+        }
+    };
+    out.push_str(&format!(
+        "response = {}(\n    {},\n    url,\n",
+        call,
+        python_str(request.method.as_str())
+    ));
+    for kwarg in kwargs.iter() {
+        out.push_str(&format!("    {},\n", kwarg));
+    }
+    out.push_str(")\nprint(response.status_code)\nprint(response.text)");
+    out
+}
+
+/// Quote an argument for a POSIX shell, leaving it bare when that is safe.
+fn shell_quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=@,+%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.to_owned()
+    } else if arg.chars().any(|c| c.is_control() && c != '\n' && c != '\t') {
+        shell_quote_bytes(arg.as_bytes())
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Quote data for a shell, using `$'...'` escapes for bytes which cannot be
+/// written inside single quotes.
+fn shell_quote_bytes(data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        Ok(text) if !text.chars().any(|c| c.is_control() && c != '\n' && c != '\t') => {
+            shell_quote(text)
+        }
+        _ => format!("$'{}'", escape_bytes(data)),
+    }
+}
+
+fn shell_quote_path(path: &Path) -> String {
+    shell_quote(&path.display().to_string())
+}
+
+/// Escape bytes for a Rust or Python byte string, or a `$'...'` string in
+/// a shell.
+fn escape_bytes(data: &[u8]) -> String {
+    data.iter()
+        .flat_map(|&b| std::ascii::escape_default(b))
+        .map(char::from)
+        .collect()
+}
+
+/// A Python string literal, which a JSON string also is.
+fn python_str(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_else(|_| format!("{:?}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    fn app(args: &[&str]) -> App {
+        App::from_iter(args.iter().chain(&["http://example.com/"]))
+    }
+
+    fn request<'a>(method: &'a Method, body: Option<&'a [u8]>) -> Request<'a> {
+        Request {
+            method,
+            url: "http://example.com/a?b=c d",
+            headers: vec![("X-Name".to_owned(), "it's".to_owned())],
+            body,
+            parts: &[],
+        }
+    }
+
+    #[test]
+    fn quotes_for_the_shell() {
+        assert_eq!(shell_quote("plain/path-1.json"), "plain/path-1.json");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote("$HOME"), "'$HOME'");
+        assert_eq!(shell_quote("two\nlines"), "'two\nlines'");
+        assert_eq!(shell_quote("bell\x07"), "$'bell\\x07'");
+        assert_eq!(shell_quote_bytes(b""), "''");
+        assert_eq!(shell_quote_bytes(b"it's"), "'it'\\''s'");
+        assert_eq!(shell_quote_bytes(b"\x00\xff'"), "$'\\x00\\xff\\''");
+    }
+
+    #[test]
+    fn exports_curl() {
+        let app = app(&["hurl", "-F", "--max-redirects", "3", "--timeout", "2.5"]);
+        let out = curl(&app, &request(&Method::PUT, Some(b"{\"a\": 1}")));
+        assert_eq!(
+            out,
+            "curl \\\n  -X PUT \\\n  'http://example.com/a?b=c d' \\\n  \
+             -H 'X-Name: it'\\''s' \\\n  --data-binary '{\"a\": 1}' \\\n  \
+             -L \\\n  --max-redirs 3 \\\n  --max-time 2.5"
+        );
+    }
+
+    #[test]
+    fn exports_curl_bodies_starting_with_at_literally() {
+        let out = curl(
+            &app(&["hurl"]),
+            &request(&Method::POST, Some(b"@/etc/passwd")),
+        );
+        assert!(out.ends_with("--data-raw @/etc/passwd"));
+        let out = curl(&app(&["hurl"]), &request(&Method::HEAD, None));
+        assert!(out.starts_with("curl \\\n  --head \\\n"));
+    }
+
+    #[test]
+    fn exports_httpie() {
+        let app = app(&["hurl", "-k", "--timeout", "2"]);
+        let out = httpie(&app, &request(&Method::POST, Some(b"x=1")));
+        assert_eq!(
+            out,
+            "http \\\n  --timeout=2 \\\n  --verify=no \\\n  --raw x=1 \\\n  POST \\\n  \
+             'http://example.com/a?b=c d' \\\n  'X-Name:it'\\''s'"
+        );
+    }
+
+    #[test]
+    fn exports_rust_reqwest() {
+        let out = rust_reqwest(&app(&["hurl"]), &request(&Method::PATCH, Some(b"\xff")));
+        assert!(out.contains(".redirect(reqwest::redirect::Policy::none())"));
+        assert!(out.contains(".request(reqwest::Method::PATCH, \"http://example.com/a?b=c d\")"));
+        assert!(out.contains(".header(\"X-Name\", \"it's\")"));
+        assert!(out.contains(".body(&b\"\\xff\"[..])"));
+
+        let method = Method::from_bytes(b"PURGE").unwrap();
+        let out = rust_reqwest(&app(&["hurl"]), &request(&method, None));
+        assert!(out.contains("reqwest::Method::from_bytes(b\"PURGE\")?"));
+    }
+
+    #[test]
+    fn exports_python_requests() {
+        let app = app(&["hurl", "-F", "--connect-timeout", "1"]);
+        let out = python_requests(&app, &request(&Method::POST, Some("é".as_bytes())));
+        assert_eq!(
+            out,
+            "import requests\n\n\
+             url = \"http://example.com/a?b=c d\"\n\
+             headers = {\n    \"X-Name\": \"it's\",\n}\n\
+             data = \"é\".encode()\n\n\
+             response = requests.request(\n    \"POST\",\n    url,\n    \
+             headers=headers,\n    data=data,\n    timeout=(1.0, None),\n)\n\
+             print(response.status_code)\nprint(response.text)"
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use crate::errors::{Error, HurlResult};
+use crate::jsonpath::{self, Segment};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// A jq-like filter for --filter, e.g. `.items[0:2]`, `.users[].name` or
+/// `.items[] | select(.price < 10) | .id`, whose paths are those of
+/// `jsonpath::parse`.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    stages: Vec<Stage>,
+}
+
+#[derive(Debug, Clone)]
+enum Stage {
+    Path(Vec<Segment>),
+    Select(Condition),
+}
+
+/// A predicate of `select`, the value at a path compared to a literal, or
+/// the value at a path being neither false nor null.
+#[derive(Debug, Clone)]
+struct Condition {
+    path: Vec<Segment>,
+    comparison: Option<(Op, Value)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+pub fn parse(src: &str) -> HurlResult<Filter> {
+    let invalid = |reason: &str| Error::InvalidFilter(src.to_owned(), reason.to_owned());
+    let mut stages = Vec::new();
+    for stage in split_outside_quotes(src, '|') {
+        let stage = stage.trim();
+        if stage.is_empty() {
+            return Err(invalid("empty filter"));
+        }
+        if let Some(inner) = stage.strip_prefix("select(") {
+            let inner = inner
+                .strip_suffix(')')
+                .ok_or_else(|| invalid("select( is not closed"))?;
+            stages.push(Stage::Select(
+                parse_condition(inner).map_err(|e| invalid(&e))?,
+            ));
+        } else {
+            stages.push(Stage::Path(
+                jsonpath::parse_segments(stage).map_err(|e| invalid(&e))?,
+            ));
+        }
+    }
+    Ok(Filter { stages })
+}
+
+/// Split `src` on `sep` where it is not inside a quoted string.
+fn split_outside_quotes(src: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (i, c) in outside_quotes(src) {
+        if c == sep {
+            parts.push(&src[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&src[start..]);
+    parts
+}
+
+/// The characters of `src` with their positions, leaving out those inside
+/// single or double quotes, where a backslash escapes the next character.
+fn outside_quotes(src: &str) -> Vec<(usize, char)> {
+    let mut found = Vec::new();
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in src.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, c) => found.push((i, c)),
+        }
+    }
+    found
+}
+
+fn parse_condition(src: &str) -> Result<Condition, String> {
+    const OPS: &[(&str, Op)] = &[
+        ("==", Op::Eq),
+        ("!=", Op::Ne),
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+    ];
+    let src = src.trim();
+    // The path ends at the first operator outside a quoted field name.
+    for (i, _) in outside_quotes(src) {
+        if let Some((token, op)) = OPS.iter().find(|(token, _)| src[i..].starts_with(token)) {
+            let path = jsonpath::parse_segments(&src[..i])?;
+            let literal = src[i + token.len()..].trim();
+            if literal.is_empty() {
+                return Err(format!("missing value after {}", token));
+            }
+            let value = serde_json::from_str(literal)
+                .map_err(|_| format!("{} is not a JSON value", literal))?;
+            return Ok(Condition {
+                path,
+                comparison: Some((*op, value)),
+            });
+        }
+    }
+    Ok(Condition {
+        path: jsonpath::parse_segments(src)?,
+        comparison: None,
+    })
+}
+
+impl Filter {
+    /// Run the filter on `value`, giving each of the values it selects.
+    pub fn apply(&self, value: &Value) -> HurlResult<Vec<Value>> {
+        let mut values = vec![value.clone()];
+        for stage in self.stages.iter() {
+            let mut next = Vec::new();
+            for value in values.iter() {
+                match stage {
+                    Stage::Path(steps) => next.extend(jsonpath::follow(value, steps)?),
+                    Stage::Select(condition) => {
+                        if condition.holds(value)? {
+                            next.push(value.clone());
+                        }
+                    }
+                }
+            }
+            values = next;
+        }
+        Ok(values)
+    }
+}
+
+impl Condition {
+    /// Whether any value at the path satisfies the condition.
+    fn holds(&self, value: &Value) -> HurlResult<bool> {
+        let found = jsonpath::follow(value, &self.path)?;
+        Ok(found.iter().any(|v| match &self.comparison {
+            None => !matches!(v, Value::Null | Value::Bool(false)),
+            Some((op, expected)) => {
+                let ordering = compare(v, expected);
+                match op {
+                    Op::Eq => v == expected,
+                    Op::Ne => v != expected,
+                    Op::Lt => ordering == Some(Ordering::Less),
+                    Op::Le => matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal)),
+                    Op::Gt => ordering == Some(Ordering::Greater),
+                    Op::Ge => matches!(ordering, Some(Ordering::Greater) | Some(Ordering::Equal)),
+                }
+            }
+        }))
+    }
+}
+
+/// Numbers and strings are ordered among themselves, anything else is not
+/// ordered.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(filter: &str, value: Value) -> Vec<Value> {
+        parse(filter).unwrap().apply(&value).unwrap()
+    }
+
+    fn field(name: &str) -> Segment {
+        Segment::Field(name.to_owned())
+    }
+
+    #[test]
+    fn parses_stages() {
+        let filter = parse(r#".items[-2:] | select(."a|b" == "x|y") | .id"#).unwrap();
+        assert_eq!(filter.stages.len(), 3);
+        match &filter.stages[0] {
+            Stage::Path(path) => {
+                assert_eq!(*path, [field("items"), Segment::Slice(Some(-2), None)])
+            }
+            stage => panic!("unexpected {:?}", stage),
+        }
+        match &filter.stages[1] {
+            Stage::Select(condition) => {
+                assert_eq!(condition.path, [field("a|b")]);
+                assert!(matches!(&condition.comparison, Some((Op::Eq, v)) if *v == json!("x|y")));
+            }
+            stage => panic!("unexpected {:?}", stage),
+        }
+    }
+
+    #[test]
+    fn parses_conditions() {
+        let condition = parse_condition(".price <= 10").unwrap();
+        assert_eq!(condition.path, [field("price")]);
+        assert!(matches!(condition.comparison, Some((Op::Le, v)) if v == json!(10)));
+
+        let condition = parse_condition(r#"['a<b'] == "x""#).unwrap();
+        assert_eq!(condition.path, [field("a<b")]);
+        assert!(matches!(condition.comparison, Some((Op::Eq, v)) if v == json!("x")));
+
+        let condition = parse_condition(".done").unwrap();
+        assert_eq!(condition.path, [field("done")]);
+        assert!(condition.comparison.is_none());
+
+        assert!(parse_condition(".a ==").is_err());
+        assert!(parse_condition(".a == nope").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_filters() {
+        for filter in &["", ".a | ", "select(.a", ".a[", ".a[x]", ".a..b"] {
+            assert!(parse(filter).is_err(), "{}", filter);
+        }
+    }
+
+    #[test]
+    fn indexes_and_slices_from_either_end() {
+        let items = json!({"items": [1, 2, 3, 4]});
+        assert_eq!(run(".items[0]", items.clone()), [json!(1)]);
+        assert_eq!(run(".items[-1]", items.clone()), [json!(4)]);
+        assert_eq!(run(".items[-5]", items.clone()), [Value::Null]);
+        assert_eq!(run(".items[-2:]", items.clone()), [json!([3, 4])]);
+        assert_eq!(run(".items[:-1]", items.clone()), [json!([1, 2, 3])]);
+        assert_eq!(run(".items[1:3]", items.clone()), [json!([2, 3])]);
+        assert_eq!(run(".items[-1:][0]", items), [json!(4)]);
+    }
+
+    #[test]
+    fn iterates_and_selects() {
+        let value = json!({"users": [
+            {"name": "a", "age": 30, "admin": true},
+            {"name": "b", "age": 17, "admin": false},
+            {"name": "c", "age": 45}
+        ]});
+        assert_eq!(
+            run(".users[].name", value.clone()),
+            [json!("a"), json!("b"), json!("c")]
+        );
+        assert_eq!(
+            run(".users[] | select(.age >= 30) | .name", value.clone()),
+            [json!("a"), json!("c")]
+        );
+        assert_eq!(
+            run(".users[] | select(.admin) | .name", value.clone()),
+            [json!("a")]
+        );
+        assert_eq!(
+            run(r#".users[] | select(.name != "b") | .age"#, value.clone()),
+            [json!(30), json!(45)]
+        );
+        assert_eq!(run(".missing.deeper", value), [Value::Null]);
+    }
+
+    #[test]
+    fn reports_steps_into_the_wrong_type() {
+        assert!(parse(".a[0]")
+            .unwrap()
+            .apply(&json!({"a": {"b": 1}}))
+            .is_err());
+        assert!(parse(".a.b").unwrap().apply(&json!({"a": [1]})).is_err());
+        assert!(parse(".[]").unwrap().apply(&json!("text")).is_err());
+    }
+}
@@ -0,0 +1,416 @@
+use crate::app::{App, HistoryCommand, Parameter};
+use crate::body::RawBody;
+use crate::client;
+use crate::cookies;
+use crate::directories::DIRECTORIES;
+use crate::errors::{Error, HurlResult};
+use crate::session::{self, Session};
+use log::warn;
+use reqwest::{Response, Url};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use syntect::highlighting::Theme;
+use syntect::parsing::SyntaxSet;
+
+/// The number of requests kept in the history.
+const MAX_ENTRIES: usize = 1000;
+
+/// A request which was sent, along with what came back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    #[serde(default)]
+    pub id: u64,
+    pub time: u64,
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub parameters: Vec<Parameter>,
+    #[serde(default)]
+    pub form: bool,
+    /// A raw body, base64 encoded when it is not UTF-8.
+    pub body: Option<String>,
+    #[serde(default)]
+    pub binary: bool,
+    /// The parameters and other parts of the request left out as secrets.
+    #[serde(default)]
+    pub redacted: Vec<String>,
+    pub session: Option<SessionName>,
+    pub status: Option<u16>,
+    pub elapsed_ms: Option<u64>,
+    pub size: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionName {
+    pub name: String,
+    pub host: String,
+}
+
+impl Entry {
+    /// The request part of an entry, without any credentials.
+    pub fn new(
+        app: &App,
+        method: &reqwest::Method,
+        url: &Url,
+        session: Option<&Session>,
+        parameters: &[Parameter],
+        body: Option<&RawBody>,
+    ) -> Self {
+        let mut redacted = Vec::new();
+        let mut url = url.clone();
+        if url.password().is_some() {
+            let _ = url.set_password(None);
+            redacted.push("URL password".to_owned());
+        }
+        if let Some(query) = url.query().map(str::to_owned) {
+            // Keep the rest of the query as it was written rather than
+            // encoding it again.
+            let kept: Vec<&str> = query
+                .split('&')
+                .filter(|pair| {
+                    let key = serde_urlencoded::from_str::<Vec<(String, String)>>(pair)
+                        .ok()
+                        .and_then(|pairs| pairs.into_iter().next())
+                        .map(|(key, _)| key)
+                        .unwrap_or_default();
+                    if session::is_sensitive_header(&key) {
+                        redacted.push(format!("query {}", key));
+                        false
+                    } else {
+                        true
+                    }
+                })
+                .collect();
+            if kept.is_empty() {
+                url.set_query(None);
+            } else {
+                url.set_query(Some(&kept.join("&")));
+            }
+        }
+        let parameters = parameters
+            .iter()
+            .filter(|p| match p {
+                Parameter::Header { key, .. }
+                | Parameter::Data { key, .. }
+                | Parameter::RawJsonData { key, .. }
+                | Parameter::Query { key, .. }
+                    if session::is_sensitive_header(key) =>
+                {
+                    redacted.push(key.clone());
+                    false
+                }
+                _ => true,
+            })
+            .cloned()
+            .collect();
+        if body.is_some() && !app.history_body {
+            redacted.push("body".to_owned());
+        }
+        let body = body.filter(|_| app.history_body);
+        let (body, binary) = match body.map(|b| String::from_utf8(b.bytes.clone())) {
+            Some(Ok(text)) => (Some(text), false),
+            Some(Err(e)) => (Some(base64::encode(e.as_bytes())), true),
+            None => (None, false),
+        };
+        Entry {
+            id: 0,
+            time: cookies::now(),
+            method: method.to_string(),
+            url: url.to_string(),
+            parameters,
+            form: app.form,
+            body,
+            binary,
+            redacted,
+            session: session.map(|s| SessionName {
+                name: s.name().to_owned(),
+                host: s.host().to_owned(),
+            }),
+            status: None,
+            elapsed_ms: None,
+            size: None,
+            error: None,
+        }
+    }
+
+    fn body(&self) -> HurlResult<Option<RawBody>> {
+        match &self.body {
+            Some(body) if self.binary => {
+                let bytes =
+                    base64::decode(body).map_err(|_| Error::InvalidHistoryEntry(self.id))?;
+                Ok(Some(RawBody::new(bytes)))
+            }
+            Some(body) => Ok(Some(RawBody::new(body.clone().into_bytes()))),
+            None => Ok(None),
+        }
+    }
+
+    fn summary(&self) -> String {
+        let status = match self.status {
+            Some(status) => status.to_string(),
+            None => "---".to_owned(),
+        };
+        let mut line = format!(
+            "{:>5}  {}  {}  {} {}",
+            self.id,
+            format_time(self.time),
+            status,
+            self.method,
+            self.url
+        );
+        if let Some(ms) = self.elapsed_ms {
+            line.push_str(&format!("  {} ms", ms));
+        }
+        if let Some(size) = self.size {
+            line.push_str(&format!("  {} bytes", size));
+        }
+        line
+    }
+
+    fn matches(&self, pattern: &str) -> bool {
+        let pattern = pattern.to_lowercase();
+        self.summary().to_lowercase().contains(&pattern)
+            || self
+                .parameters
+                .iter()
+                .any(|p| p.to_string().to_lowercase().contains(&pattern))
+    }
+}
+
+/// Add the outcome of sending a request to its entry and append it to the
+/// history. Failing to do so only warns, as the request itself went fine.
+pub fn record(app: &App, mut entry: Entry, result: &HurlResult<Response>, elapsed: Duration) {
+    if app.no_history {
+        return;
+    }
+    entry.elapsed_ms = Some(elapsed.as_millis() as u64);
+    match result {
+        Ok(resp) => {
+            entry.status = Some(resp.status().as_u16());
+            entry.size = resp.content_length();
+        }
+        Err(e) => entry.error = Some(e.to_string()),
+    }
+    if let Err(e) = append(entry) {
+        warn!("Could not write to the history: {}", e);
+    }
+}
+
+fn history_file() -> PathBuf {
+    DIRECTORIES.config().join("history.jsonl")
+}
+
+/// All entries, oldest first. Lines which cannot be read are skipped.
+fn load() -> HurlResult<Vec<Entry>> {
+    let path = history_file();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::File(path, e)),
+    };
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+fn append(mut entry: Entry) -> HurlResult<()> {
+    let path = history_file();
+    let mut entries = load()?;
+    entry.id = entries.last().map(|e| e.id + 1).unwrap_or(1);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| Error::FileWrite(dir.to_owned(), e))?;
+    }
+
+    if entries.len() < MAX_ENTRIES {
+        let mut file = private_file(&path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)
+            .map_err(|e| Error::FileWrite(path.clone(), e))?;
+        return Ok(());
+    }
+
+    entries.drain(..=entries.len() - MAX_ENTRIES);
+    entries.push(entry);
+    let mut content = String::new();
+    for entry in entries.iter() {
+        content.push_str(&serde_json::to_string(entry)?);
+        content.push('\n');
+    }
+    session::write_private(&path, content.as_bytes())
+}
+
+/// Open the history file for appending, creating it readable only by its
+/// owner.
+fn private_file(path: &Path) -> HurlResult<fs::File> {
+    let mut options = OpenOptions::new();
+    options.append(true).create(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .map_err(|e| Error::FileWrite(path.to_owned(), e))
+}
+
+fn find(id: u64) -> HurlResult<Entry> {
+    load()?
+        .into_iter()
+        .find(|e| e.id == id)
+        .ok_or(Error::HistoryEntryNotFound(id))
+}
+
+pub fn run_command(
+    app: &mut App,
+    ss: &SyntaxSet,
+    theme: &Theme,
+    cmd: &HistoryCommand,
+) -> HurlResult<()> {
+    match cmd {
+        HistoryCommand::List { pattern, limit } => {
+            let entries: Vec<Entry> = load()?
+                .into_iter()
+                .filter(|e| pattern.as_ref().map(|p| e.matches(p)).unwrap_or(true))
+                .collect();
+            let skip = entries.len().saturating_sub(*limit);
+            for entry in entries.iter().skip(skip) {
+                println!("{}", entry.summary());
+            }
+            Ok(())
+        }
+        HistoryCommand::Show { id } => {
+            print(&find(*id)?);
+            Ok(())
+        }
+        HistoryCommand::Rerun { id } => rerun(app, ss, theme, find(*id)?),
+        HistoryCommand::Clear => match fs::remove_file(history_file()) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(Error::File(history_file(), e)),
+            _ => Ok(()),
+        },
+    }
+}
+
+fn print(entry: &Entry) {
+    println!("Request {} sent {}", entry.id, format_time(entry.time));
+    println!("{} {}", entry.method, entry.url);
+    if !entry.parameters.is_empty() {
+        println!("Parameters:");
+        for parameter in entry.parameters.iter() {
+            println!("    {}", parameter);
+        }
+    }
+    if entry.form {
+        println!("Form: yes");
+    }
+    if let Some(body) = &entry.body {
+        if entry.binary {
+            println!("Body: binary, {} bytes base64 encoded", body.len());
+        } else {
+            println!("Body:\n{}", body);
+        }
+    }
+    if let Some(session) = &entry.session {
+        println!("Session: {} for {}", session.name, session.host);
+    }
+    if !entry.redacted.is_empty() {
+        println!("Redacted: {}", entry.redacted.join(", "));
+    }
+    match (entry.status, &entry.error) {
+        (Some(status), _) => println!("Status: {}", status),
+        (None, Some(error)) => println!("Error: {}", error),
+        (None, None) => {}
+    }
+    if let Some(ms) = entry.elapsed_ms {
+        println!("Time: {} ms", ms);
+    }
+    if let Some(size) = entry.size {
+        println!("Size: {} bytes", size);
+    }
+}
+
+/// Send a request from the history again. Options given on the command
+/// line apply to it, and it uses the session it was sent with unless
+/// --session names another.
+fn rerun(app: &mut App, ss: &SyntaxSet, theme: &Theme, entry: Entry) -> HurlResult<()> {
+    if !entry.redacted.is_empty() {
+        warn!(
+            "Not sending {}, which were left out of the history",
+            entry.redacted.join(", ")
+        );
+    }
+    app.form = app.form || entry.form;
+    let method = crate::app::parse_method(&entry.method)?;
+    let host = match &entry.session {
+        Some(session) => session.host.clone(),
+        None => session::host_key(app, &entry.url)?,
+    };
+    let name = app
+        .session
+        .clone()
+        .or_else(|| entry.session.as_ref().map(|s| s.name.clone()));
+    let mut session = name
+        .map(|name| Session::get_or_create(app, name, host, &entry.url))
+        .transpose()?;
+
+    let prepared = client::prepare(
+        app,
+        method,
+        &mut session,
+        &entry.url,
+        &entry.parameters,
+        entry.body()?,
+    )?;
+    if let Some(mut exchange) = crate::send(app, ss, theme, prepared, &mut session)? {
+        crate::handle_response(app, ss, theme, &mut exchange, &mut session, &[])?;
+    }
+    Ok(())
+}
+
+/// A date and time in UTC.
+pub struct DateTime {
+    pub year: i64,
+    pub month: i64,
+    pub day: i64,
+    pub hour: u64,
+    pub minute: u64,
+    pub second: u64,
+}
+
+impl DateTime {
+    pub fn from_secs(secs: u64) -> Self {
+        let days = (secs / 86400) as i64;
+        let rest = secs % 86400;
+        // Convert days since 1970-01-01 to a civil date.
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        DateTime {
+            year,
+            month,
+            day,
+            hour: rest / 3600,
+            minute: rest % 3600 / 60,
+            second: rest % 60,
+        }
+    }
+}
+
+/// Format seconds since the epoch as a UTC date and time.
+fn format_time(secs: u64) -> String {
+    let t = DateTime::from_secs(secs);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        t.year, t.month, t.day, t.hour, t.minute, t.second
+    )
+}
@@ -0,0 +1,1127 @@
+use crate::app::{self, App, ImportCommand, ImportTarget, Parameter};
+use crate::auth::AuthType;
+use crate::body::RawBody;
+use crate::client::{self, Prepared};
+use crate::errors::{Error, HurlResult};
+use crate::session::{self, Session};
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use syntect::highlighting::Theme;
+use syntect::parsing::SyntaxSet;
+
+/// A request translated from a curl command or a HAR entry.
+pub struct Import {
+    pub method: reqwest::Method,
+    pub url: String,
+    pub parameters: Vec<Parameter>,
+    pub body: Option<RawBody>,
+    pub options: Options,
+}
+
+/// The options of an imported request which hurl has flags for.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub form: bool,
+    pub auth: Option<String>,
+    pub token: Option<String>,
+    pub auth_type: Option<AuthType>,
+    pub aws_region: Option<String>,
+    pub aws_service: Option<String>,
+    pub follow: bool,
+    pub max_redirects: Option<usize>,
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub proxy: Option<String>,
+    pub cacert: Option<PathBuf>,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub insecure: bool,
+}
+
+impl Options {
+    fn from_app(app: &App) -> Self {
+        Options {
+            form: app.form,
+            auth: app.auth.clone(),
+            token: app.token.clone(),
+            auth_type: app.auth_type,
+            aws_region: app.aws_region.clone(),
+            aws_service: app.aws_service.clone(),
+            follow: app.follow,
+            max_redirects: app.max_redirects,
+            timeout: app.timeout,
+            connect_timeout: app.connect_timeout,
+            proxy: app.proxy.clone(),
+            cacert: app.cacert.clone(),
+            cert: app.cert.clone(),
+            key: app.key.clone(),
+            insecure: app.insecure,
+        }
+    }
+
+    /// Fill in the options which were not given on the command line with
+    /// the imported ones.
+    fn or(&self, imported: &Options) -> Options {
+        Options {
+            form: self.form || imported.form,
+            auth: self.auth.clone().or_else(|| imported.auth.clone()),
+            token: self.token.clone().or_else(|| imported.token.clone()),
+            auth_type: self.auth_type.or(imported.auth_type),
+            aws_region: self.aws_region.clone().or_else(|| imported.aws_region.clone()),
+            aws_service: self
+                .aws_service
+                .clone()
+                .or_else(|| imported.aws_service.clone()),
+            follow: self.follow || imported.follow,
+            max_redirects: self.max_redirects.or(imported.max_redirects),
+            timeout: self.timeout.or(imported.timeout),
+            connect_timeout: self.connect_timeout.or(imported.connect_timeout),
+            proxy: self.proxy.clone().or_else(|| imported.proxy.clone()),
+            cacert: self.cacert.clone().or_else(|| imported.cacert.clone()),
+            cert: self.cert.clone().or_else(|| imported.cert.clone()),
+            key: self.key.clone().or_else(|| imported.key.clone()),
+            insecure: self.insecure || imported.insecure,
+        }
+    }
+
+    fn apply(self, app: &mut App) {
+        app.form = self.form;
+        app.auth = self.auth;
+        app.token = self.token;
+        app.auth_type = self.auth_type;
+        app.aws_region = self.aws_region;
+        app.aws_service = self.aws_service;
+        app.follow = self.follow;
+        app.max_redirects = self.max_redirects;
+        app.timeout = self.timeout;
+        app.connect_timeout = self.connect_timeout;
+        app.proxy = self.proxy;
+        app.cacert = self.cacert;
+        app.cert = self.cert;
+        app.key = self.key;
+        app.insecure = self.insecure;
+    }
+}
+
+pub fn run(app: &mut App, ss: &SyntaxSet, theme: &Theme, cmd: &ImportCommand) -> HurlResult<()> {
+    let (imports, target) = match cmd {
+        ImportCommand::Curl { command, target } => {
+            let command = if command == "-" {
+                let mut buffer = String::new();
+                io::stdin().read_to_string(&mut buffer)?;
+                buffer
+            } else {
+                command.clone()
+            };
+            (vec![from_curl(&command)?], target)
+        }
+        ImportCommand::Har {
+            file,
+            entries,
+            target,
+        } => (from_har(file, entries)?, target),
+    };
+
+    let base = Options::from_app(app);
+    let mut sessions: HashMap<String, Session> = HashMap::new();
+    let mut file = String::new();
+    for (i, import) in imports.into_iter().enumerate() {
+        base.or(&import.options).apply(app);
+        let host = session::host_key(app, &import.url)?;
+        let mut session = match &app.session {
+            Some(name) => match sessions.remove(&host) {
+                Some(s) => Some(s),
+                None => Some(Session::get_or_create(
+                    app,
+                    name.clone(),
+                    host.clone(),
+                    &import.url,
+                )?),
+            },
+            None => None,
+        };
+
+        if target.run {
+            if i > 0 && !app.json_output() {
+                println!();
+            }
+            if app.export.is_none() && !app.json_output() {
+                println!("{} {}", import.method, import.url);
+            }
+            let prepared = client::prepare(
+                app,
+                import.method,
+                &mut session,
+                &import.url,
+                &import.parameters,
+                import.body,
+            )?;
+            if let Some(mut exchange) = crate::send(app, ss, theme, prepared, &mut session)? {
+                crate::handle_response(app, ss, theme, &mut exchange, &mut session, &[])?;
+            }
+        } else {
+            if app.auth.is_some() || app.token.is_some() {
+                warn!("Leaving credentials out of the request file, give them when running it");
+            }
+            let prepared = client::prepare_without_auth(
+                app,
+                import.method,
+                &import.url,
+                &import.parameters,
+                import.body,
+            )?;
+            if i > 0 {
+                file.push_str("\n###\n");
+            }
+            file.push_str(&request_text(&prepared)?);
+            if let Some(s) = &mut session {
+                if !app.read_only {
+                    s.update_with_parameters(&import.parameters);
+                    s.update_auth(app.auth_type, &app.auth, &app.token);
+                    s.save(app)?;
+                }
+            }
+        }
+
+        if let Some(s) = session {
+            sessions.insert(host, s);
+        }
+    }
+
+    if !target.run {
+        write_file(target, &file)?;
+    }
+    Ok(())
+}
+
+fn write_file(target: &ImportTarget, file: &str) -> HurlResult<()> {
+    match &target.output {
+        Some(path) => fs::write(path, file).map_err(|e| Error::FileWrite(path.clone(), e)),
+        None => {
+            print!("{}", file);
+            Ok(())
+        }
+    }
+}
+
+/// A prepared request in the format read by `hurl run`.
+fn request_text(prepared: &Prepared) -> HurlResult<String> {
+    if !prepared.parts.is_empty() {
+        return Err(Error::UnsupportedImport(
+            "a multipart body cannot be written to a request file, use --run".to_owned(),
+        ));
+    }
+    let request = &prepared.request;
+    let mut text = format!("{} {}\n", request.method(), request.url());
+    for (key, value) in request.headers().iter() {
+        text.push_str(&format!(
+            "{}: {}\n",
+            crate::header_name(key.as_str()),
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+    if let Some(body) = &prepared.body {
+        let body = std::str::from_utf8(body).map_err(|_| {
+            Error::UnsupportedImport(
+                "a binary body cannot be written to a request file, use --run".to_owned(),
+            )
+        })?;
+        text.push('\n');
+        text.push_str(body);
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// Translate a curl command, such as one from "Copy as cURL" in a browser.
+pub fn from_curl(command: &str) -> HurlResult<Import> {
+    let invalid = |msg: &str| Error::InvalidCurl(msg.to_owned());
+    let mut words = split_words(command)?.into_iter().peekable();
+    if words
+        .peek()
+        .map(|w| w == "curl" || w == "curl.exe" || w.ends_with("/curl"))
+        .unwrap_or(false)
+    {
+        words.next();
+    }
+
+    let mut curl = Curl::default();
+    while let Some(word) = words.next() {
+        if word == "--" {
+            curl.urls.extend(words.by_ref());
+        } else if let Some(name) = word.strip_prefix("--") {
+            let value = if takes_value(name) {
+                Some(
+                    words
+                        .next()
+                        .ok_or_else(|| invalid(&format!("--{} needs a value", name)))?,
+                )
+            } else {
+                None
+            };
+            curl.option(name, value)?;
+        } else if word.len() > 1 && word.starts_with('-') {
+            // Short options can be combined, as in -sSL, and the value of
+            // the last one can follow it directly, as in -XPOST.
+            for (i, c) in word.char_indices().skip(1) {
+                let name = match short_option(c) {
+                    Some(name) => name,
+                    None => {
+                        warn!("Ignoring curl option -{}", c);
+                        continue;
+                    }
+                };
+                if takes_value(name) {
+                    let rest = &word[i + c.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        words
+                            .next()
+                            .ok_or_else(|| invalid(&format!("-{} needs a value", c)))?
+                    } else {
+                        rest.to_owned()
+                    };
+                    curl.option(name, Some(value))?;
+                    break;
+                }
+                curl.option(name, None)?;
+            }
+        } else {
+            curl.urls.push(word);
+        }
+    }
+    curl.finish()
+}
+
+/// The options of a curl command which are kept.
+#[derive(Default)]
+struct Curl {
+    urls: Vec<String>,
+    method: Option<reqwest::Method>,
+    head: bool,
+    get: bool,
+    headers: Vec<(String, String)>,
+    cookies: Vec<String>,
+    data: Vec<String>,
+    form: Vec<Parameter>,
+    upload: Option<PathBuf>,
+    options: Options,
+}
+
+/// The long name of a short curl option.
+fn short_option(c: char) -> Option<&'static str> {
+    let name = match c {
+        'X' => "request",
+        'H' => "header",
+        'd' => "data",
+        'F' => "form",
+        'u' => "user",
+        'b' => "cookie",
+        'A' => "user-agent",
+        'e' => "referer",
+        'x' => "proxy",
+        'm' => "max-time",
+        'T' => "upload-file",
+        'E' => "cert",
+        'I' => "head",
+        'G' => "get",
+        'L' => "location",
+        'k' => "insecure",
+        'o' => "output",
+        'w' => "write-out",
+        'c' => "cookie-jar",
+        'D' => "dump-header",
+        'r' => "range",
+        'U' => "proxy-user",
+        's' => "silent",
+        'S' => "show-error",
+        'v' => "verbose",
+        'i' => "include",
+        'f' => "fail",
+        'g' => "globoff",
+        'N' => "no-buffer",
+        'O' => "remote-name",
+        '#' => "progress-bar",
+        '4' => "ipv4",
+        '6' => "ipv6",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn takes_value(name: &str) -> bool {
+    matches!(
+        name,
+        "request"
+            | "header"
+            | "data"
+            | "data-raw"
+            | "data-binary"
+            | "data-ascii"
+            | "data-urlencode"
+            | "form"
+            | "form-string"
+            | "user"
+            | "cookie"
+            | "user-agent"
+            | "referer"
+            | "url"
+            | "proxy"
+            | "max-time"
+            | "connect-timeout"
+            | "max-redirs"
+            | "upload-file"
+            | "cert"
+            | "key"
+            | "cacert"
+            | "oauth2-bearer"
+            | "aws-sigv4"
+            | "output"
+            | "write-out"
+            | "cookie-jar"
+            | "dump-header"
+            | "range"
+            | "proxy-user"
+            | "retry"
+            | "resolve"
+            | "connect-to"
+            | "cert-type"
+            | "key-type"
+            | "limit-rate"
+            | "interface"
+    )
+}
+
+impl Curl {
+    fn option(&mut self, name: &str, value: Option<String>) -> HurlResult<()> {
+        let value = value.unwrap_or_default();
+        match name {
+            "request" => self.method = Some(app::parse_method(&value)?),
+            "head" => self.head = true,
+            "get" => self.get = true,
+            "header" => {
+                // `Name;` sends an empty header, while `Name:` with no value
+                // only removes a header curl would otherwise send.
+                if let Some(key) = value.strip_suffix(';').filter(|k| !k.contains(':')) {
+                    self.headers.push((key.trim().to_owned(), String::new()));
+                } else if let Some(idx) = value.find(':') {
+                    let (key, rest) = value.split_at(idx);
+                    let rest = rest[1..].trim();
+                    if !rest.is_empty() {
+                        self.headers.push((key.trim().to_owned(), rest.to_owned()));
+                    }
+                } else {
+                    return Err(Error::InvalidCurl(format!("invalid header {}", value)));
+                }
+            }
+            "user-agent" => self.headers.push(("User-Agent".to_owned(), value)),
+            "referer" => {
+                let referer = value.trim_end_matches(";auto").to_owned();
+                if !referer.is_empty() {
+                    self.headers.push(("Referer".to_owned(), referer));
+                }
+            }
+            "cookie" if value.contains('=') => self.cookies.push(value),
+            "cookie" => warn!("Ignoring cookie file {}", value),
+            "data" | "data-ascii" => match value.strip_prefix('@') {
+                Some(path) => {
+                    let text = read_text(path)?;
+                    self.data.push(text.replace(['\r', '\n'], ""));
+                }
+                None => self.data.push(value),
+            },
+            "data-binary" => match value.strip_prefix('@') {
+                Some(path) => self.data.push(read_text(path)?),
+                None => self.data.push(value),
+            },
+            "data-raw" => self.data.push(value),
+            "data-urlencode" => self.data.push(urlencode_data(&value)?),
+            "form" => self.form.push(form_part(&value)?),
+            "form-string" => {
+                let (key, value) = split_form(&value)?;
+                self.form.push(Parameter::Data {
+                    key: escape_key(key),
+                    value: value.to_owned(),
+                });
+            }
+            "upload-file" => self.upload = Some(PathBuf::from(value)),
+            "url" => self.urls.push(value),
+            "user" => self.options.auth = Some(value),
+            "oauth2-bearer" => self.options.token = Some(value),
+            "basic" => self.options.auth_type = Some(AuthType::Basic),
+            "digest" => self.options.auth_type = Some(AuthType::Digest),
+            "aws-sigv4" => {
+                // Given as provider1[:provider2[:region[:service]]].
+                let mut parts = value.split(':').skip(2);
+                self.options.auth_type = Some(AuthType::SigV4);
+                self.options.aws_region = parts.next().map(str::to_owned);
+                self.options.aws_service = parts.next().map(str::to_owned);
+            }
+            "location" | "location-trusted" => self.options.follow = true,
+            "max-redirs" => {
+                // curl uses -1 for no limit, which hurl has no setting for.
+                if let Ok(max) = value.parse() {
+                    self.options.max_redirects = Some(max);
+                }
+            }
+            "max-time" => self.options.timeout = Some(app::parse_seconds(&value)?),
+            "connect-timeout" => self.options.connect_timeout = Some(app::parse_seconds(&value)?),
+            "proxy" => self.options.proxy = Some(value),
+            "cacert" => self.options.cacert = Some(PathBuf::from(value)),
+            "cert" => self.options.cert = Some(PathBuf::from(value)),
+            "key" => self.options.key = Some(PathBuf::from(value)),
+            "insecure" => self.options.insecure = true,
+            "compressed" | "silent" | "show-error" | "verbose" | "include" | "fail" | "globoff"
+            | "no-buffer" | "progress-bar" | "http1.1" | "http2" | "ipv4" | "ipv6" => {
+                debug!("Ignoring curl option --{}", name)
+            }
+            _ => warn!("Ignoring curl option --{}", name),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> HurlResult<Import> {
+        let mut urls = self.urls.into_iter();
+        let url = urls
+            .next()
+            .ok_or_else(|| Error::InvalidCurl("no URL given".to_owned()))?;
+        if urls.next().is_some() {
+            warn!("Only importing the first URL of the curl command");
+        }
+        // curl uses http when the URL has no scheme.
+        let url = if url.contains("://") {
+            url
+        } else {
+            format!("http://{}", url)
+        };
+        let (mut url, mut parameters) = split_url(&url);
+        let mut options = self.options;
+
+        let is_multipart = self.form.iter().any(Parameter::is_form_file);
+        for (key, value) in self.headers.into_iter() {
+            if is_multipart && key.eq_ignore_ascii_case("content-type") {
+                continue;
+            }
+            parameters.push(Parameter::Header { key, value });
+        }
+        if !self.cookies.is_empty() {
+            parameters.push(Parameter::Header {
+                key: "Cookie".to_owned(),
+                value: self.cookies.join("; "),
+            });
+        }
+
+        let has_body = !self.data.is_empty() || !self.form.is_empty() || self.upload.is_some();
+        let method = match self.method {
+            Some(method) => method,
+            None if self.head => reqwest::Method::HEAD,
+            None if self.upload.is_some() => reqwest::Method::PUT,
+            None if has_body && !self.get => reqwest::Method::POST,
+            None => reqwest::Method::GET,
+        };
+
+        let mut body = None;
+        if let Some(path) = &self.upload {
+            body = Some(RawBody::from_file(path)?);
+        } else if !self.form.is_empty() {
+            options.form = true;
+            parameters.extend(self.form);
+        } else if !self.data.is_empty() {
+            let text = self.data.join("&");
+            if self.get {
+                match query_parameters(&text) {
+                    Some(query) => parameters.extend(query),
+                    None => {
+                        url.push(if url.contains('?') { '&' } else { '?' });
+                        url.push_str(&text);
+                    }
+                }
+            } else {
+                // curl sends data as a form unless told otherwise.
+                let content_type = header_value(&parameters, "content-type");
+                let mime = content_type.unwrap_or("application/x-www-form-urlencoded");
+                match body_parameters(mime, &text) {
+                    Some((data, form)) => {
+                        options.form = form;
+                        parameters.extend(data);
+                    }
+                    None if content_type.is_none() => {
+                        body = Some(RawBody {
+                            bytes: text.into_bytes(),
+                            content_type: Some("application/x-www-form-urlencoded"),
+                        })
+                    }
+                    None => body = Some(RawBody::new(text.into_bytes())),
+                }
+            }
+        }
+
+        Ok(Import {
+            method,
+            url,
+            parameters,
+            body,
+            options,
+        })
+    }
+}
+
+fn read_text(path: &str) -> HurlResult<String> {
+    if path == "-" {
+        let mut buffer = String::new();
+        io::stdin().read_to_string(&mut buffer)?;
+        return Ok(buffer);
+    }
+    fs::read_to_string(path).map_err(|e| Error::File(path.into(), e))
+}
+
+/// The data for `--data-urlencode`, which is one of `content`, `=content`,
+/// `name=content`, `@file` or `name@file`.
+fn urlencode_data(value: &str) -> HurlResult<String> {
+    let encode = |s: &str| serde_urlencoded::to_string([("", s)]).map(|e| e[1..].to_owned());
+    let (name, content) = match (value.find('='), value.find('@')) {
+        (Some(eq), at) if at.map(|at| eq < at).unwrap_or(true) => {
+            (&value[..eq], value[eq + 1..].to_owned())
+        }
+        (_, Some(at)) => (&value[..at], read_text(&value[at + 1..])?),
+        _ => ("", value.to_owned()),
+    };
+    let content = encode(&content).map_err(Error::FormEncoding)?;
+    if name.is_empty() {
+        Ok(content)
+    } else {
+        Ok(format!("{}={}", name, content))
+    }
+}
+
+fn split_form(value: &str) -> HurlResult<(&str, &str)> {
+    match value.find('=') {
+        Some(idx) => Ok((&value[..idx], &value[idx + 1..])),
+        None => Err(Error::InvalidCurl(format!("invalid form field {}", value))),
+    }
+}
+
+/// A `--form` field, where `name=@file` uploads a file and `name=<file`
+/// sends the contents of a file as the value.
+fn form_part(value: &str) -> HurlResult<Parameter> {
+    let (key, value) = split_form(value)?;
+    let key = escape_key(key);
+    // Drop attributes such as `;type=image/png`.
+    let path = |v: &str| v.split(';').next().unwrap_or("").to_owned();
+    if let Some(file) = value.strip_prefix('@') {
+        Ok(Parameter::FormFile {
+            key,
+            filename: path(file),
+        })
+    } else if let Some(file) = value.strip_prefix('<') {
+        Ok(Parameter::Data {
+            key,
+            value: read_text(&path(file))?,
+        })
+    } else {
+        Ok(Parameter::Data {
+            key,
+            value: value.to_owned(),
+        })
+    }
+}
+
+/// Split a URL into the URL without its query and the query parameters. A
+/// query which cannot be written as parameters stays on the URL.
+fn split_url(url: &str) -> (String, Vec<Parameter>) {
+    let url = url.split('#').next().unwrap_or("");
+    let split = url
+        .find('?')
+        .and_then(|idx| Some((&url[..idx], query_parameters(&url[idx + 1..])?)));
+    match split {
+        Some((url, parameters)) => (url.to_owned(), parameters),
+        None => (url.to_owned(), Vec::new()),
+    }
+}
+
+/// The parameters of a query, or `None` if they would not send the same
+/// query, e.g. because a pair has no `=` or is not UTF-8 once decoded.
+fn query_parameters(query: &str) -> Option<Vec<Parameter>> {
+    if query
+        .split('&')
+        .any(|pair| !pair.is_empty() && !pair.contains('='))
+    {
+        return None;
+    }
+    let pairs: Vec<(String, String)> = serde_urlencoded::from_str(query).ok()?;
+    let lossy = |s: &String| s.contains('\u{fffd}') && !query.contains('\u{fffd}');
+    if pairs.iter().any(|(key, value)| lossy(key) || lossy(value)) {
+        return None;
+    }
+    Some(
+        pairs
+            .into_iter()
+            .map(|(key, value)| Parameter::Query { key, value })
+            .collect(),
+    )
+}
+
+fn header_value<'a>(parameters: &'a [Parameter], name: &str) -> Option<&'a str> {
+    parameters.iter().find_map(|p| match p {
+        Parameter::Header { key, value } if key.eq_ignore_ascii_case(name) => Some(value.as_str()),
+        _ => None,
+    })
+}
+
+/// Turn a JSON object or form body into data parameters, returning whether
+/// they make a form. A body which would not come out the same, such as a
+/// form which repeats a field, is left as it is.
+fn body_parameters(content_type: &str, text: &str) -> Option<(Vec<Parameter>, bool)> {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    if mime == "application/x-www-form-urlencoded" {
+        if text.split('&').any(|pair| !pair.contains('=')) {
+            return None;
+        }
+        let pairs: Vec<(String, String)> = serde_urlencoded::from_str(text).ok()?;
+        let mut keys = HashSet::new();
+        if pairs
+            .iter()
+            .any(|(k, _)| k.is_empty() || !keys.insert(k.as_str()))
+        {
+            return None;
+        }
+        let data = pairs
+            .into_iter()
+            .map(|(key, value)| Parameter::Data {
+                key: escape_key(&key),
+                value,
+            })
+            .collect();
+        Some((data, true))
+    } else if mime == "application/json" || mime.ends_with("+json") {
+        let object = match serde_json::from_str(text).ok()? {
+            Value::Object(object) => object,
+            _ => return None,
+        };
+        if object.is_empty() || object.keys().any(String::is_empty) {
+            return None;
+        }
+        let data = object
+            .into_iter()
+            .map(|(key, value)| match value {
+                Value::String(value) => Parameter::Data {
+                    key: escape_key(&key),
+                    value,
+                },
+                value => Parameter::RawJsonData {
+                    key: escape_key(&key),
+                    value: value.to_string(),
+                },
+            })
+            .collect();
+        Some((data, false))
+    } else {
+        None
+    }
+}
+
+/// Escape a field name so that it is not read as a data path.
+fn escape_key(key: &str) -> String {
+    let mut escaped = String::with_capacity(key.len());
+    for c in key.chars() {
+        if let '\\' | '.' | '[' | ']' = c {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Split a shell command into words, following the quoting used by "Copy as
+/// cURL": single and double quotes, `$'...'` strings and backslash escapes,
+/// where a backslash at the end of a line continues the command.
+fn split_words(command: &str) -> HurlResult<Vec<String>> {
+    let unterminated = || Error::InvalidCurl("unterminated quote".to_owned());
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('\n') | None => {}
+                Some('\r') if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                }
+                Some(escaped) => word.get_or_insert_with(String::new).push(escaped),
+            },
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(unterminated()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('\n') => {}
+                            Some(c) if c == '"' || c == '\\' || c == '$' || c == '`' => {
+                                word.push(c)
+                            }
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(unterminated()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(unterminated()),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                let bytes = ansi_c_string(&mut chars).ok_or_else(unterminated)?;
+                word.get_or_insert_with(String::new)
+                    .push_str(&String::from_utf8_lossy(&bytes));
+            }
+            c if c.is_whitespace() => {
+                if let Some(word) = word.take() {
+                    words.push(word);
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(word) = word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Read the rest of a `$'...'` string. Escapes such as `\xe2` give bytes
+/// rather than characters, so the result may need decoding as UTF-8.
+fn ansi_c_string<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let push = |bytes: &mut Vec<u8>, c: char| {
+        let mut buf = [0; 4];
+        bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    };
+    loop {
+        match chars.next()? {
+            '\'' => return Some(bytes),
+            '\\' => {
+                let c = chars.next()?;
+                match c {
+                    'n' => bytes.push(b'\n'),
+                    't' => bytes.push(b'\t'),
+                    'r' => bytes.push(b'\r'),
+                    'a' => bytes.push(0x07),
+                    'b' => bytes.push(0x08),
+                    'e' | 'E' => bytes.push(0x1b),
+                    'f' => bytes.push(0x0c),
+                    'v' => bytes.push(0x0b),
+                    'x' => bytes.push(hex_digits(chars, 2)? as u8),
+                    'u' => push(&mut bytes, std::char::from_u32(hex_digits(chars, 4)?)?),
+                    'U' => push(&mut bytes, std::char::from_u32(hex_digits(chars, 8)?)?),
+                    '0'..='7' => {
+                        let mut value = c.to_digit(8)?;
+                        for _ in 0..2 {
+                            match chars.peek().and_then(|c| c.to_digit(8)) {
+                                Some(digit) => {
+                                    value = value * 8 + digit;
+                                    chars.next();
+                                }
+                                None => break,
+                            }
+                        }
+                        bytes.push(value as u8);
+                    }
+                    c => push(&mut bytes, c),
+                }
+            }
+            c => push(&mut bytes, c),
+        }
+    }
+}
+
+/// Read up to `max` hex digits, of which there must be at least one.
+fn hex_digits<I: Iterator<Item = char>>(
+    chars: &mut std::iter::Peekable<I>,
+    max: usize,
+) -> Option<u32> {
+    let mut value = None;
+    for _ in 0..max {
+        match chars.peek().and_then(|c| c.to_digit(16)) {
+            Some(digit) => {
+                value = Some(value.unwrap_or(0) * 16 + digit);
+                chars.next();
+            }
+            None => break,
+        }
+    }
+    value
+}
+
+#[derive(Debug, Deserialize)]
+struct Har {
+    log: HarLog,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarLog {
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarEntry {
+    request: HarRequest,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: Vec<HarHeader>,
+    post_data: Option<HarPostData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarPostData {
+    #[serde(default)]
+    mime_type: String,
+    text: Option<String>,
+    #[serde(default)]
+    params: Vec<HarParam>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarParam {
+    name: String,
+    value: Option<String>,
+    file_name: Option<String>,
+}
+
+/// Translate the requests recorded in a HAR file, or only the entries at
+/// the given positions counting from 1.
+pub fn from_har(path: &Path, positions: &[usize]) -> HurlResult<Vec<Import>> {
+    let content = if path == Path::new("-") {
+        let mut buffer = String::new();
+        io::stdin().read_to_string(&mut buffer)?;
+        buffer
+    } else {
+        fs::read_to_string(path).map_err(|e| Error::File(path.to_owned(), e))?
+    };
+    let har: Har =
+        serde_json::from_str(&content).map_err(|e| Error::InvalidHar(path.to_owned(), e))?;
+    let mut entries: Vec<Option<HarEntry>> = har.log.entries.into_iter().map(Some).collect();
+    let count = entries.len();
+
+    let requests = if positions.is_empty() {
+        entries.into_iter().flatten().map(|e| e.request).collect()
+    } else {
+        let mut requests = Vec::new();
+        for &position in positions.iter() {
+            let entry = position
+                .checked_sub(1)
+                .and_then(|idx| entries.get_mut(idx))
+                .and_then(Option::take)
+                .ok_or(Error::UnknownHarEntry(position, count))?;
+            requests.push(entry.request);
+        }
+        requests
+    };
+    requests.into_iter().map(har_request).collect()
+}
+
+/// Headers which the browser sets for the connection rather than the request.
+fn is_connection_header(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.starts_with(':')
+        || name == "host"
+        || name == "connection"
+        || name == "content-length"
+        || name == "accept-encoding"
+}
+
+fn har_request(request: HarRequest) -> HurlResult<Import> {
+    let method = app::parse_method(&request.method)?;
+    let (url, mut parameters) = split_url(&request.url);
+    let mut options = Options::default();
+    let post_data = request.post_data;
+    let is_multipart = post_data
+        .as_ref()
+        .map(|p| p.params.iter().any(|p| p.file_name.is_some()))
+        .unwrap_or(false);
+
+    for header in request.headers.into_iter() {
+        if is_connection_header(&header.name)
+            || (is_multipart && header.name.eq_ignore_ascii_case("content-type"))
+        {
+            continue;
+        }
+        parameters.push(Parameter::Header {
+            key: header.name,
+            value: header.value,
+        });
+    }
+
+    let mut body = None;
+    if let Some(post_data) = post_data {
+        if is_multipart {
+            options.form = true;
+            for param in post_data.params.into_iter() {
+                let key = escape_key(&param.name);
+                match param.file_name {
+                    Some(filename) => {
+                        warn!(
+                            "HAR files do not hold uploaded files, {} will be read from the current directory",
+                            filename
+                        );
+                        parameters.push(Parameter::FormFile { key, filename });
+                    }
+                    None => parameters.push(Parameter::Data {
+                        key,
+                        value: param.value.unwrap_or_default(),
+                    }),
+                }
+            }
+        } else if let Some(text) = post_data.text.filter(|t| !t.is_empty()) {
+            match body_parameters(&post_data.mime_type, &text) {
+                Some((data, form)) => {
+                    options.form = form;
+                    parameters.extend(data);
+                }
+                None => {
+                    if header_value(&parameters, "content-type").is_none()
+                        && !post_data.mime_type.is_empty()
+                    {
+                        parameters.push(Parameter::Header {
+                            key: "Content-Type".to_owned(),
+                            value: post_data.mime_type.clone(),
+                        });
+                    }
+                    body = Some(RawBody::new(text.into_bytes()));
+                }
+            }
+        }
+    }
+
+    Ok(Import {
+        method,
+        url,
+        parameters,
+        body,
+        options,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(command: &str) -> Vec<String> {
+        split_words(command).unwrap()
+    }
+
+    #[test]
+    fn splits_plain_and_quoted_words() {
+        assert_eq!(words("curl  -s\thttp://x"), ["curl", "-s", "http://x"]);
+        assert_eq!(words("a'b c'd"), ["ab cd"]);
+        assert_eq!(words("'it'\\''s'"), ["it's"]);
+        assert_eq!(words("''"), [""]);
+        assert_eq!(words(r#""a \"b\" \$c \\ \n""#), [r#"a "b" $c \ \n"#]);
+        assert_eq!(words(r#""'single'""#), ["'single'"]);
+        assert_eq!(words(r"a\ b \'"), ["a b", "'"]);
+    }
+
+    #[test]
+    fn joins_continued_lines() {
+        assert_eq!(
+            words("curl 'http://x' \\\n  -H 'A: b' \\\r\n  --compressed"),
+            ["curl", "http://x", "-H", "A: b", "--compressed"]
+        );
+        assert_eq!(words("\"a\\\nb\""), ["ab"]);
+    }
+
+    #[test]
+    fn decodes_ansi_c_strings() {
+        assert_eq!(words(r"$'a\nb\tc'"), ["a\nb\tc"]);
+        assert_eq!(words(r"$'\x41\101é\U0001F600'"), ["AA\u{e9}\u{1F600}"]);
+        assert_eq!(words(r"$'\xe2\x82\xac'"), ["\u{20ac}"]);
+        assert_eq!(words(r"$'it\'s \\ ok'"), ["it's \\ ok"]);
+        assert_eq!(words(r"x$'\e'y"), ["x\u{1b}y"]);
+        assert_eq!(words("$x"), ["$x"]);
+    }
+
+    #[test]
+    fn rejects_unterminated_quotes() {
+        for command in &["'a", "\"a", "\"a\\", "$'a", r"$'\x'"] {
+            assert!(split_words(command).is_err(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn translates_a_browser_command() {
+        let import = from_curl(
+            "curl 'https://example.com/api?x=1' \\\n  -H 'Accept: application/json' \\\n  \
+             -H $'X-Note: caf\\u00e9' \\\n  --data-raw '{\"a\":1}' \\\n  --compressed",
+        )
+        .unwrap();
+        assert_eq!(import.method, reqwest::Method::POST);
+        assert_eq!(import.url, "https://example.com/api");
+        let parameters: Vec<String> = import.parameters.iter().map(|p| p.to_string()).collect();
+        assert!(parameters.contains(&"x==1".to_owned()));
+        assert!(parameters.contains(&"Accept:application/json".to_owned()));
+        assert!(parameters.contains(&"X-Note:caf\u{e9}".to_owned()));
+        assert_eq!(import.body.unwrap().bytes, b"{\"a\":1}");
+    }
+
+    #[test]
+    fn reads_combined_short_options() {
+        let import = from_curl("curl -sSLXPUT http://x").unwrap();
+        assert_eq!(import.method, reqwest::Method::PUT);
+        assert!(import.options.follow);
+        assert!(from_curl("curl http://x -H").is_err());
+    }
+
+    #[test]
+    fn keeps_queries_which_are_not_parameters_on_the_url() {
+        let import = from_curl("curl 'http://x/a?q=a+b&n=1#top'").unwrap();
+        assert_eq!(import.url, "http://x/a");
+        let parameters: Vec<String> = import.parameters.iter().map(|p| p.to_string()).collect();
+        assert_eq!(parameters, ["q==a b", "n==1"]);
+
+        for url in &["http://x/a?flag&n=1", "http://x/a?n=%ff"] {
+            let import = from_curl(&format!("curl '{}'", url)).unwrap();
+            assert_eq!(import.url, *url);
+            assert!(import.parameters.is_empty());
+        }
+
+        let import = from_curl("curl -G 'http://x/a?n=1' -d flag").unwrap();
+        assert_eq!(import.method, reqwest::Method::GET);
+        assert_eq!(import.url, "http://x/a?flag");
+        assert_eq!(import.parameters[0].to_string(), "n==1");
+    }
+}
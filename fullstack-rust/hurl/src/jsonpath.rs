@@ -0,0 +1,357 @@
+use crate::errors::{Error, HurlResult};
+use serde_json::Value;
+
+/// A single step in a path through a JSON document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Field(String),
+    /// An index into an array, counting back from the end when negative.
+    Index(i64),
+    /// The elements from the first index up to the second, either of which
+    /// may be left out or count back from the end.
+    Slice(Option<i64>, Option<i64>),
+    /// Every element of an array or value of an object.
+    Each,
+}
+
+/// Parse a path such as `$.data.items[0].id`, `.data.id` or `data["id"]`.
+///
+/// The leading `$` is optional, as is the leading dot. Besides fields and
+/// indexes a path may hold negative indexes such as `[-1]`, slices such as
+/// `[1:3]` or `[-2:]`, and `[]`, `[*]` or `.*` for each element. A field
+/// name may be quoted, as in `["a.b"]`, `['a.b']` or `."a.b"`, where a
+/// backslash takes the character after it as it is.
+pub fn parse(path: &str) -> HurlResult<Vec<Segment>> {
+    parse_segments(path).map_err(|_| Error::InvalidJsonPath(path.to_owned()))
+}
+
+/// Parse a path like `parse`, giving the reason it is invalid on failure.
+pub fn parse_segments(path: &str) -> Result<Vec<Segment>, String> {
+    let path = path.trim();
+    let mut segments = Vec::new();
+    let mut rest = path.strip_prefix('$').unwrap_or(path);
+    if rest.starts_with('.') && !rest.starts_with("..") {
+        rest = &rest[1..];
+    }
+
+    while !rest.is_empty() {
+        if let Some(inner) = rest.strip_prefix('[') {
+            // A quoted name may hold a ], so the bracket closes after it.
+            let trimmed = inner.trim_start();
+            let start = match trimmed.chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => {
+                    inner.len() - trimmed.len() + quoted_name(trimmed)?.1
+                }
+                _ => 0,
+            };
+            let end = start + inner[start..].find(']').ok_or("[ is not closed")?;
+            segments.push(parse_bracket(inner[..end].trim())?);
+            rest = &inner[end + 1..];
+        } else if rest.starts_with('"') || rest.starts_with('\'') {
+            let (name, len) = quoted_name(rest)?;
+            segments.push(Segment::Field(name));
+            rest = &rest[len..];
+        } else if let Some(after) = rest.strip_prefix('*') {
+            segments.push(Segment::Each);
+            rest = after;
+        } else {
+            let end = rest.find(&['.', '['][..]).unwrap_or(rest.len());
+            let field = &rest[..end];
+            if field.is_empty() {
+                return Err(format!("missing field name in {}", path));
+            }
+            segments.push(Segment::Field(field.to_owned()));
+            rest = &rest[end..];
+        }
+        if let Some(after) = rest.strip_prefix('.') {
+            rest = after;
+            if rest.is_empty() || rest.starts_with('.') || rest.starts_with('[') {
+                return Err(format!("missing field name in {}", path));
+            }
+        } else if !rest.is_empty() && !rest.starts_with('[') {
+            return Err(format!("unexpected {} in {}", rest, path));
+        }
+    }
+    Ok(segments)
+}
+
+/// The content of `[...]`: nothing or `*` for each element, an index, a
+/// slice or a quoted field name.
+fn parse_bracket(inner: &str) -> Result<Segment, String> {
+    if inner.is_empty() || inner == "*" {
+        return Ok(Segment::Each);
+    }
+    if inner.starts_with('"') || inner.starts_with('\'') {
+        let (name, len) = quoted_name(inner)?;
+        if len != inner.len() {
+            return Err(format!("unexpected text after [{}", inner));
+        }
+        return Ok(Segment::Field(name));
+    }
+    let number = |s: &str| {
+        s.trim()
+            .parse::<i64>()
+            .map_err(|_| format!("{} is not an index", s.trim()))
+    };
+    let bound = |s: &str| {
+        if s.trim().is_empty() {
+            Ok(None)
+        } else {
+            number(s).map(Some)
+        }
+    };
+    match inner.find(':') {
+        Some(idx) => Ok(Segment::Slice(
+            bound(&inner[..idx])?,
+            bound(&inner[idx + 1..])?,
+        )),
+        None => Ok(Segment::Index(number(inner)?)),
+    }
+}
+
+/// Split `src` after the path at its start, which ends at the first
+/// whitespace outside brackets and quoted names.
+pub fn split_path(src: &str) -> (&str, &str) {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut depth = 0;
+    for (i, c) in src.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            (None, c) if c.is_whitespace() && depth <= 0 => return src.split_at(i),
+            _ => {}
+        }
+    }
+    (src, "")
+}
+
+/// Read a name in single or double quotes at the start of `src`, returning
+/// it and the length of the source it took up.
+fn quoted_name(src: &str) -> Result<(String, usize), String> {
+    let mut chars = src.char_indices();
+    let quote = match chars.next() {
+        Some((_, quote)) => quote,
+        None => return Err("missing field name".to_owned()),
+    };
+    let mut name = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, escaped)) => name.push(escaped),
+                None => break,
+            },
+            c if c == quote => return Ok((name, i + 1)),
+            c => name.push(c),
+        }
+    }
+    Err("a quoted field name is not closed".to_owned())
+}
+
+/// Follow `path` through `value`, returning `None` if some step does not
+/// exist. A slice gives an array, as does each element, which holds the
+/// rest of the path followed from every element where it exists.
+pub fn select(value: &Value, path: &str) -> HurlResult<Option<Value>> {
+    Ok(lookup(value, &parse(path)?))
+}
+
+fn lookup(value: &Value, segments: &[Segment]) -> Option<Value> {
+    let (segment, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => return Some(value.clone()),
+    };
+    match segment {
+        Segment::Field(name) => lookup(value.get(name)?, rest),
+        Segment::Index(idx) => {
+            let items = value.as_array()?;
+            lookup(items.get(position(*idx, items.len())?)?, rest)
+        }
+        Segment::Slice(from, to) => {
+            let items = value.as_array()?;
+            lookup(&Value::Array(slice(items, *from, *to).to_vec()), rest)
+        }
+        Segment::Each => {
+            let items: Vec<&Value> = match value {
+                Value::Array(items) => items.iter().collect(),
+                Value::Object(map) => map.values().collect(),
+                _ => return None,
+            };
+            let found = items.into_iter().filter_map(|v| lookup(v, rest));
+            Some(Value::Array(found.collect()))
+        }
+    }
+}
+
+/// Follow `segments` from `value` the way jq does, giving every value they
+/// lead to. A missing field or index gives null, while stepping into a
+/// value of the wrong type is an error.
+pub fn follow(value: &Value, segments: &[Segment]) -> HurlResult<Vec<Value>> {
+    let mut values = vec![value.clone()];
+    for segment in segments.iter() {
+        let mut next = Vec::new();
+        for value in values.into_iter() {
+            match (segment, value) {
+                (Segment::Field(_), Value::Null)
+                | (Segment::Index(_), Value::Null)
+                | (Segment::Slice(..), Value::Null) => next.push(Value::Null),
+                (Segment::Field(name), Value::Object(mut map)) => {
+                    next.push(map.remove(name).unwrap_or(Value::Null))
+                }
+                (Segment::Index(idx), Value::Array(items)) => {
+                    let value = position(*idx, items.len()).and_then(|i| items.into_iter().nth(i));
+                    next.push(value.unwrap_or(Value::Null));
+                }
+                (Segment::Slice(from, to), Value::Array(items)) => {
+                    next.push(Value::Array(slice(&items, *from, *to).to_vec()))
+                }
+                (Segment::Each, Value::Array(items)) => next.extend(items),
+                (Segment::Each, Value::Object(map)) => next.extend(map.into_iter().map(|(_, v)| v)),
+                (segment, value) => {
+                    return Err(Error::FilterType(describe(segment), type_name(&value)));
+                }
+            }
+        }
+        values = next;
+    }
+    Ok(values)
+}
+
+/// The position of an index which may count back from the end.
+fn position(idx: i64, len: usize) -> Option<usize> {
+    let idx = if idx < 0 { len as i64 + idx } else { idx };
+    if idx < 0 {
+        None
+    } else {
+        Some(idx as usize)
+    }
+}
+
+fn slice(items: &[Value], from: Option<i64>, to: Option<i64>) -> &[Value] {
+    let clamp = |idx: i64| {
+        let idx = if idx < 0 {
+            items.len() as i64 + idx
+        } else {
+            idx
+        };
+        idx.max(0).min(items.len() as i64) as usize
+    };
+    let from = clamp(from.unwrap_or(0));
+    let to = clamp(to.unwrap_or(items.len() as i64)).max(from);
+    &items[from..to]
+}
+
+fn describe(segment: &Segment) -> String {
+    match segment {
+        Segment::Field(name) => format!("field {}", name),
+        Segment::Index(idx) => format!("index {}", idx),
+        Segment::Slice(..) => "a slice".to_owned(),
+        Segment::Each => "[]".to_owned(),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use Segment::*;
+
+    fn field(name: &str) -> Segment {
+        Field(name.to_owned())
+    }
+
+    #[test]
+    fn parses_paths() {
+        assert_eq!(parse("$").unwrap(), []);
+        assert_eq!(parse(".").unwrap(), []);
+        assert_eq!(
+            parse("$.data.items[0].id").unwrap(),
+            [field("data"), field("items"), Index(0), field("id")]
+        );
+        assert_eq!(parse("data[\"id\"]").unwrap(), [field("data"), field("id")]);
+        assert_eq!(
+            parse(".a[-1][-2:][:-1][ 1 : 3 ][][*].*").unwrap(),
+            [
+                field("a"),
+                Index(-1),
+                Slice(Some(-2), None),
+                Slice(None, Some(-1)),
+                Slice(Some(1), Some(3)),
+                Each,
+                Each,
+                Each
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_quoted_names() {
+        assert_eq!(
+            parse(r#"."a.b"['c]'][ "d\"e" ]"#).unwrap(),
+            [field("a.b"), field("c]"), field("d\"e")]
+        );
+        assert_eq!(parse(r"['it\'s']").unwrap(), [field("it's")]);
+    }
+
+    #[test]
+    fn splits_paths_from_what_follows() {
+        assert_eq!(split_path(".a.b == 1"), (".a.b", " == 1"));
+        assert_eq!(
+            split_path(".a['odd key'][ 1 : 2 ] > 0"),
+            (".a['odd key'][ 1 : 2 ]", " > 0")
+        );
+        assert_eq!(split_path(".a"), (".a", ""));
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        for path in &[
+            ".a.", ".a..b", ".a.[0]", ".a[0", ".a[x]", ".a[1:y]", r#"."a"#, ".a[0]b", "['a'x]",
+        ] {
+            assert!(parse(path).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn selects_values() {
+        let value = json!({"items": [{"id": 1}, {"id": 2}, {"name": "c"}], "n": null});
+        let select = |path: &str| select(&value, path).unwrap();
+        assert_eq!(select("$.items[1].id"), Some(json!(2)));
+        assert_eq!(select(".items[-1].name"), Some(json!("c")));
+        assert_eq!(select(".items[-4]"), None);
+        assert_eq!(select(".items[:2][1].id"), Some(json!(2)));
+        assert_eq!(select(".items[].id"), Some(json!([1, 2])));
+        assert_eq!(select(".n"), Some(Value::Null));
+        assert_eq!(select(".missing"), None);
+        assert_eq!(select(".n.deeper"), None);
+    }
+
+    #[test]
+    fn follows_like_jq() {
+        let value = json!({"items": [1, 2, 3, 4]});
+        let follow = |path: &str| follow(&value, &parse(path).unwrap()).unwrap();
+        assert_eq!(follow(".items[-1]"), [json!(4)]);
+        assert_eq!(follow(".items[-2:]"), [json!([3, 4])]);
+        assert_eq!(follow(".items[:-1]"), [json!([1, 2, 3])]);
+        assert_eq!(follow(".items[-9:2]"), [json!([1, 2])]);
+        assert_eq!(follow(".items[3:1]"), [json!([])]);
+        assert_eq!(follow(".items[9]"), [Value::Null]);
+        assert_eq!(follow(".missing.deeper"), [Value::Null]);
+        assert!(super::follow(&value, &parse(".items.a").unwrap()).is_err());
+    }
+}
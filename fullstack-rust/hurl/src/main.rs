@@ -1,12 +1,31 @@
-use structopt::StructOpt;
 use heck::TitleCase;
-use log::trace;
+use log::{debug, warn};
+use std::time::Instant;
 
 mod app;
+mod assertions;
+mod auth;
+mod bench;
+mod body;
 mod client;
+mod completions;
 mod config;
+mod cookies;
+mod credentials;
+mod datapath;
 mod directories;
+mod download;
 mod errors;
+mod export;
+mod filter;
+mod history;
+mod import;
+mod jsonpath;
+mod man;
+mod oauth;
+mod render;
+mod script;
+mod secrets;
 mod session;
 
 use errors::HurlResult;
@@ -15,13 +34,41 @@ use syntect::highlighting::Theme;
 use syntect::parsing::SyntaxSet;
 
 mod syntax;
+mod timing;
+mod tls;
 
 type OrderedJson = std::collections::BTreeMap<String, serde_json::Value>;
 
-fn main() -> HurlResult<()> {
-    let mut app = app::App::from_args();
+/// A response along with how long it took and the redirects followed to
+/// get it.
+pub struct Exchange {
+    pub resp: reqwest::Response,
+    pub timing: timing::Timing,
+    pub redirects: Vec<client::Redirect>,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        errors::report(&e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+fn run() -> HurlResult<()> {
+    let mut app = app::App::parse_args();
     app.validate()?;
-    app.process_config_file();
+    if let Some(app::Command::Config(cmd)) = &app.cmd {
+        let cmd = cmd.clone();
+        return config::run_command(&mut app, &cmd);
+    }
+    if let Some(app::Command::Completions(data)) = &app.cmd {
+        return completions::run(&app, data);
+    }
+    if let Some(app::Command::Man) = &app.cmd {
+        return man::print();
+    }
+    app.process_config_file()?;
+    app.unlock_secrets()?;
 
     if let Some(level) = app.log_level() {
         std::env::set_var("RUST_LOG", format!("hurl={}", level));
@@ -31,37 +78,205 @@ fn main() -> HurlResult<()> {
     let (ss, ts) = syntax::build()?;
     let theme = &ts.themes["Solarized (dark)"];
 
-    let mut session = app
-        .session
-        .as_ref()
-        .map(|name| session::Session::get_or_create(&app, name.clone(), app.host()));
+    if let Some(app::Command::Import(cmd)) = &app.cmd {
+        let cmd = cmd.clone();
+        return import::run(&mut app, &ss, theme, &cmd);
+    }
+    if let Some(app::Command::History(cmd)) = &app.cmd {
+        let cmd = cmd.clone();
+        return history::run_command(&mut app, &ss, theme, &cmd);
+    }
+
+    let mut session = match app.cmd {
+        Some(app::Command::Method(_)) | None => app
+            .session
+            .as_ref()
+            .map(|name| {
+                session::Session::get_or_create(&app, name.clone(), app.host()?, app.target())
+            })
+            .transpose()?,
+        _ => None,
+    };
 
     match app.cmd {
-        Some(ref method) => {
-            let resp = client::perform_method(&app, method, &mut session)?;
-            handle_response(&app, &ss, theme, resp, &mut session)
+        Some(app::Command::Run(ref data)) => script::run(&app, &ss, theme, data),
+        Some(app::Command::Session(ref cmd)) => session::run_command(&app, cmd),
+        Some(app::Command::Config(_))
+        | Some(app::Command::Completions(_))
+        | Some(app::Command::Man)
+        | Some(app::Command::Import(_))
+        | Some(app::Command::History(_)) => unreachable!(),
+        Some(app::Command::Encrypt { ref value }) => {
+            let store = app.secrets.as_ref().ok_or(errors::Error::SecretLocked)?;
+            let value = match value {
+                Some(v) => v.clone(),
+                None => credentials::read_password("Secret: ")?
+                    .ok_or(errors::Error::NoTerminal("secret"))?,
+            };
+            println!("{}", store.encrypt(&value)?);
+            Ok(())
+        }
+        Some(app::Command::Method(ref method)) => {
+            let prepared = client::prepare_method(&app, method, &mut session)?;
+            if let Some(mut exchange) = send(&app, &ss, theme, prepared, &mut session)? {
+                handle_response(&app, &ss, theme, &mut exchange, &mut session, &[])?;
+            }
+            Ok(())
         }
         None => {
             let url = app.url.take().unwrap();
             let has_data = app.parameters.iter().any(|p| p.is_data());
-            let method = if has_data {
-                reqwest::Method::POST
+            let body = body::from_app(&app)?;
+            let method = match app.method.clone() {
+                Some(method) => method,
+                None if has_data || body.is_some() => reqwest::Method::POST,
+                None => reqwest::Method::GET,
+            };
+            let prepared =
+                client::prepare(&app, method, &mut session, &url, &app.parameters, body)?;
+            if let Some(mut exchange) = send(&app, &ss, theme, prepared, &mut session)? {
+                handle_response(&app, &ss, theme, &mut exchange, &mut session, &[])?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Print the parts of the request selected by --print, then send it unless
+/// running with --offline. With --export the request is only exported, and
+/// with --repeat it is sent repeatedly and only a summary is printed.
+///
+/// A 401 response is answered once, with Digest credentials for
+/// --auth-type digest or by renewing the token in the session with OAuth2.
+fn send(
+    app: &app::App,
+    ss: &SyntaxSet,
+    theme: &Theme,
+    prepared: client::Prepared,
+    session: &mut Option<session::Session>,
+) -> HurlResult<Option<Exchange>> {
+    if let Some(format) = app.export {
+        println!("{}", export::render(app, format, &prepared));
+        return Ok(None);
+    }
+    let print = app.print();
+    let request = &prepared.request;
+    if print.request_headers {
+        let url = request.url();
+        let mut target = url.path().to_owned();
+        if let Some(query) = url.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+        let mut s = format!("{} {} HTTP/1.1\n", request.method(), target);
+        let mut headers = Vec::new();
+        if let Some(host) = url.host_str() {
+            match url.port() {
+                Some(port) => headers.push(format!("Host: {}:{}", host, port)),
+                None => headers.push(format!("Host: {}", host)),
+            }
+        }
+        for (key, value) in app.default_headers.iter() {
+            if !request.headers().contains_key(key.as_str()) {
+                headers.push(format!("{}: {}", header_name(key), value));
+            }
+        }
+        for (key, value) in request.headers().iter() {
+            headers.push(format!(
+                "{}: {}",
+                header_name(key.as_str()),
+                value.to_str().unwrap_or("BAD HEADER VALUE")
+            ));
+        }
+        if let Some(body) = &prepared.body {
+            headers.push(format!("Content-Length: {}", body.len()));
+        }
+        s.push_str(&headers.join("\n"));
+        highlight_string(app, ss, theme, "HTTP", &s);
+    }
+    if print.request_body {
+        if let Some(body) = &prepared.body {
+            if print.request_headers {
+                println!();
+            }
+            let kind = render::Kind::detect(request.headers(), body);
+            render::body(app, ss, theme, kind, body)?;
+        } else if request.body().is_some() {
+            println!("[multipart body not shown]");
+        }
+    }
+
+    if app.offline {
+        return Ok(None);
+    }
+    if print.request_headers || print.request_body {
+        println!();
+    }
+    if let Some(count) = app.repeat {
+        bench::run(app, prepared, count)?;
+        return Ok(None);
+    }
+    let mut timing = if app.timing {
+        timing::probe(app, prepared.request.url())
+    } else {
+        timing::Timing::default()
+    };
+    let entry = prepared.entry.clone();
+    let oauth = oauth::is_enabled(app);
+    let digest = auth::is_digest(app) && !oauth;
+    let retry = if oauth || digest {
+        prepared.repeatable()
+    } else {
+        None
+    };
+    let (auth, body) = if digest {
+        (prepared.auth.clone(), prepared.body.clone())
+    } else {
+        (None, None)
+    };
+    let redirects = std::sync::Arc::clone(&prepared.redirects);
+    let start = Instant::now();
+    let mut result = prepared.send();
+    if let (Ok(resp), Some((client, mut request))) = (&result, retry) {
+        if resp.status() == reqwest::StatusCode::UNAUTHORIZED {
+            let authorization = if digest {
+                debug!("Answering the Digest challenge of a 401 response");
+                auth::digest(auth.as_deref(), resp, &request, body.as_deref())?
             } else {
-                reqwest::Method::GET
+                debug!("Renewing the OAuth2 token after a 401 response");
+                Some(format!("Bearer {}", oauth::renew(app, session)?))
             };
-            let resp = client::perform(&app, method, &mut session, &url, &app.parameters)?;
-            handle_response(&app, &ss, theme, resp, &mut session)
+            if let Some(authorization) = authorization {
+                let value = reqwest::header::HeaderValue::from_str(&authorization)
+                    .map_err(|_| errors::Error::InvalidHeader("Authorization".to_owned()))?;
+                request
+                    .headers_mut()
+                    .insert(reqwest::header::AUTHORIZATION, value);
+                result = client.execute(request).map_err(From::from);
+            }
         }
     }
+    timing.response = start.elapsed();
+    history::record(app, entry, &result, timing.response);
+    let redirects = redirects.lock().map(|r| r.clone()).unwrap_or_default();
+    result.map(|resp| {
+        Some(Exchange {
+            resp,
+            timing,
+            redirects,
+        })
+    })
 }
 
 fn handle_response(
     app: &app::App,
     ss: &SyntaxSet,
     theme: &Theme,
-    mut resp: reqwest::Response,
+    exchange: &mut Exchange,
     session: &mut Option<session::Session>,
-) -> HurlResult<()> {
+    expectations: &[assertions::Assertion],
+) -> HurlResult<String> {
+    let resp = &mut exchange.resp;
     let status = resp.status();
     let mut s = format!(
         "{:?} {} {}\n",
@@ -71,49 +286,110 @@ fn handle_response(
     );
     let mut headers = Vec::new();
     for (key, value) in resp.headers().iter() {
-        let nice_key = key.as_str().to_title_case().replace(' ', "-");
+        let nice_key = header_name(key.as_str());
         headers.push(format!(
             "{}: {}",
             nice_key,
             value.to_str().unwrap_or("BAD HEADER VALUE")
         ));
     }
-    let result = resp.text()?;
-    let content_length = match resp.content_length() {
-        Some(len) => len,
-        None => result.len() as u64,
-    };
-    headers.push(format!("Content-Length: {}", content_length));
-    headers.sort();
-    s.push_str(&(&headers[..]).join("\n"));
-    highlight_string(ss, theme, "HTTP", &s);
-
-    println!("");
-    let result_json: serde_json::Result<OrderedJson> = serde_json::from_str(&result);
-    match result_json {
-        Ok(result_value) => {
-            let result_str = serde_json::to_string_pretty(&result_value)?;
-            highlight_string(ss, theme, "JSON", &result_str);
+    let print = app.print();
+    let body = if app.download {
+        headers.sort();
+        s.push_str(&(&headers[..]).join("\n"));
+        if print.response_headers {
+            highlight_string(app, ss, theme, "HTTP", &s);
+        }
+        if app.filter.is_some() {
+            warn!("Not filtering a downloaded body");
         }
-        Err(e) => {
-            trace!("Failed to parse result to JSON: {}", e);
-            println!("{}", result);
+        let start = Instant::now();
+        download::save(app, resp)?;
+        exchange.timing.transfer = Some(start.elapsed());
+        None
+    } else {
+        let mut body = Vec::new();
+        let start = Instant::now();
+        resp.copy_to(&mut body)?;
+        exchange.timing.transfer = Some(start.elapsed());
+        let content_length = match resp.content_length() {
+            Some(len) => len,
+            None => body.len() as u64,
+        };
+        headers.push(format!("Content-Length: {}", content_length));
+        headers.sort();
+        s.push_str(&(&headers[..]).join("\n"));
+        if print.response_headers {
+            highlight_string(app, ss, theme, "HTTP", &s);
+        }
+
+        if print.response_body {
+            if print.response_headers {
+                println!("");
+            }
+            match &app.filter {
+                Some(filter) => render::filtered(app, ss, theme, filter, &body)?,
+                None => {
+                    let kind = render::Kind::detect(resp.headers(), &body);
+                    render::body(app, ss, theme, kind, &body)?;
+                }
+            }
         }
+        Some(body)
+    };
+
+    if app.json_output() {
+        render::exchange(app, ss, theme, exchange, body.as_deref())?;
+    } else if app.timing {
+        exchange.timing.print();
     }
+    let resp = &exchange.resp;
+    let result = body
+        .map(|body| String::from_utf8_lossy(&body).into_owned())
+        .unwrap_or_default();
 
     if !app.read_only {
         if let Some(s) = session {
-            s.update_with_response(&resp);
+            s.update_with_response(resp);
             s.save(app)?;
         }
     }
-    Ok(())
+
+    let mut checks = app.assertions();
+    checks.extend(expectations.iter());
+    if app.download {
+        // The body went to a file, so only status and header checks apply.
+        checks.retain(|check| match check {
+            assertions::Assertion::Json { .. } | assertions::Assertion::Body(_) => {
+                warn!("Skipping check on downloaded body: {}", check);
+                false
+            }
+            _ => true,
+        });
+    }
+    assertions::check(&checks, resp, &result)?;
+    if app.check_status && (status.is_client_error() || status.is_server_error()) {
+        return Err(errors::Error::ClientWithStatus(status));
+    }
+    Ok(result)
+}
+
+/// Format a header name as `Content-Type` rather than `content-type`.
+fn header_name(name: &str) -> String {
+    name.to_title_case().replace(' ', "-")
 }
 
-fn highlight_string(ss: &SyntaxSet, theme: &Theme, syntax: &str, string: &str) {
+/// Print `string` highlighted as `syntax`, or as it is without colour.
+fn highlight_string(app: &app::App, ss: &SyntaxSet, theme: &Theme, syntax: &str, string: &str) {
     use syntect::easy::HighlightLines;
     use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
 
+    if !app.color() {
+        print!("{}", string);
+        println!();
+        return;
+    }
+
     let syn = ss
         .find_syntax_by_name(syntax)
         .expect(&format!("{} syntax should exist", syntax));
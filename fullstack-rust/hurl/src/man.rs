@@ -0,0 +1,164 @@
+use crate::app::App;
+use crate::errors::HurlResult;
+use structopt::clap::ErrorKind;
+use structopt::StructOpt;
+
+/// Print the man page to stdout.
+pub fn print() -> HurlResult<()> {
+    print!("{}", render());
+    Ok(())
+}
+
+/// Build a man page from the long help of hurl and of each of its
+/// subcommands, so that it always matches the options hurl accepts.
+pub fn render() -> String {
+    let top = Help::parse(&long_help(&[]));
+    let mut out = format!(
+        ".TH HURL 1 \"\" \"hurl {}\" \"User Commands\"\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    out.push_str(".SH NAME\nhurl \\- ");
+    out.push_str(&escape(top.about.first().map(String::as_str).unwrap_or("")));
+    out.push('\n');
+
+    let mut commands = Vec::new();
+    for (title, lines) in top.sections.iter() {
+        match title.as_str() {
+            "USAGE" => {
+                out.push_str(".SH SYNOPSIS\n");
+                for line in lines.iter().filter(|l| !l.trim().is_empty()) {
+                    out.push_str(&format!("{}\n.br\n", escape(line.trim())));
+                }
+            }
+            "SUBCOMMANDS" => {
+                out.push_str(".SH COMMANDS\n");
+                for line in lines.iter() {
+                    let mut parts = line.trim().splitn(2, ' ');
+                    let name = parts.next().unwrap_or("");
+                    if name.is_empty() || name == "help" {
+                        continue;
+                    }
+                    out.push_str(&format!(".TP\n\\fB{}\\fR\n", escape(name)));
+                    match parts.next().map(str::trim).filter(|d| !d.is_empty()) {
+                        Some(about) => out.push_str(&format!("{}\n", escape(about))),
+                        None => out.push_str("Send a request with this method.\n"),
+                    }
+                    // The method subcommands share the options documented above.
+                    if !name.chars().all(|c| c.is_ascii_uppercase()) {
+                        commands.push(name.to_owned());
+                    }
+                }
+            }
+            _ => {
+                out.push_str(&format!(".SH {}\n", title));
+                entries(&mut out, lines);
+            }
+        }
+    }
+
+    for name in commands.iter() {
+        let help = Help::parse(&long_help(&[name]));
+        out.push_str(&format!(".SH \"HURL {}\"\n", name.to_uppercase()));
+        for line in help.about.iter() {
+            if line.is_empty() {
+                out.push_str(".PP\n");
+            } else {
+                out.push_str(&format!("{}\n", escape(line)));
+            }
+        }
+        for (title, lines) in help.sections.iter() {
+            if title == "USAGE" {
+                for line in lines.iter().filter(|l| !l.trim().is_empty()) {
+                    out.push_str(&format!(".PP\n\\fB{}\\fR\n", escape(line.trim())));
+                }
+            } else {
+                out.push_str(&format!(".SS {}\n", title));
+                entries(&mut out, lines);
+            }
+        }
+    }
+    out
+}
+
+/// The long help of the subcommand given by `path`, without wrapping.
+fn long_help(path: &[&str]) -> String {
+    let mut args = vec!["hurl"];
+    args.extend_from_slice(path);
+    args.push("--help");
+    match App::clap().set_term_width(0).get_matches_from_safe(args) {
+        Err(e) if e.kind == ErrorKind::HelpDisplayed => e.message,
+        _ => String::new(),
+    }
+}
+
+/// Help text split into the description and the titled sections.
+struct Help {
+    about: Vec<String>,
+    sections: Vec<(String, Vec<String>)>,
+}
+
+impl Help {
+    fn parse(text: &str) -> Help {
+        let mut about = Vec::new();
+        let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+        // The first line is the name and version.
+        for line in text.lines().skip(1) {
+            let is_title = line.ends_with(':')
+                && line
+                    .trim_end_matches(':')
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c == ' ');
+            if is_title && !line.starts_with(' ') {
+                sections.push((line.trim_end_matches(':').to_owned(), Vec::new()));
+            } else if let Some((_, lines)) = sections.last_mut() {
+                lines.push(line.to_owned());
+            } else if !line.trim().is_empty() || !about.is_empty() {
+                about.push(line.trim().to_owned());
+            }
+        }
+        while about.last().map(String::is_empty).unwrap_or(false) {
+            about.pop();
+        }
+        Help { about, sections }
+    }
+}
+
+/// Write the options or arguments of a section, each one a tagged paragraph.
+fn entries(out: &mut String, lines: &[String]) {
+    let mut blank = false;
+    for line in lines.iter() {
+        let indent = line.len() - line.trim_start().len();
+        let text = line.trim();
+        if text.is_empty() {
+            blank = true;
+        } else if indent <= 8 {
+            // An option such as `    -o, --output <output>    `, which may be
+            // followed by its description on the same line.
+            let mut parts = text.splitn(2, "    ");
+            out.push_str(&format!(
+                ".TP\n\\fB{}\\fR\n",
+                escape(parts.next().unwrap_or("").trim())
+            ));
+            if let Some(about) = parts.next().map(str::trim).filter(|a| !a.is_empty()) {
+                out.push_str(&format!("{}\n", escape(about)));
+            }
+            blank = false;
+        } else {
+            if blank {
+                out.push_str(".IP\n");
+                blank = false;
+            }
+            out.push_str(&format!("{}\n", escape(text)));
+        }
+    }
+}
+
+/// Escape text for roff.
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
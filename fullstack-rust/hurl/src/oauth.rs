@@ -0,0 +1,173 @@
+use crate::app::App;
+use crate::client;
+use crate::cookies;
+use crate::errors::{Error, HurlResult};
+use crate::session::Session;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+/// Renew a token this many seconds before it expires, so that it does not
+/// expire on the way to the server.
+const EXPIRY_MARGIN: u64 = 30;
+
+/// An OAuth2 token as kept in a session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Token {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// Seconds since the epoch.
+    pub expires_at: Option<u64>,
+}
+
+impl Token {
+    fn is_fresh(&self) -> bool {
+        match self.expires_at {
+            Some(at) => cookies::now() + EXPIRY_MARGIN < at,
+            None => true,
+        }
+    }
+}
+
+/// The response of a token endpoint.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+/// An error response of a token endpoint.
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+enum Grant {
+    ClientCredentials,
+    Password,
+}
+
+pub fn is_enabled(app: &App) -> bool {
+    app.oauth_token_url.is_some()
+}
+
+/// The access token to send. The one in the session is used while it is
+/// fresh, otherwise it is refreshed or a new one is fetched and kept in the
+/// session.
+///
+/// With --offline or --export no token is fetched, so only a token already
+/// in the session is used.
+pub fn access_token(app: &App, session: &mut Option<Session>) -> HurlResult<Option<String>> {
+    let current = session.as_ref().and_then(|s| s.oauth_token().cloned());
+    if app.offline || app.export.is_some() {
+        if current.is_none() {
+            warn!("Not fetching an OAuth2 token without sending the request");
+        }
+        return Ok(current.map(|t| t.access_token));
+    }
+    match current {
+        Some(token) if token.is_fresh() => Ok(Some(token.access_token)),
+        _ => renew(app, session).map(Some),
+    }
+}
+
+/// Refresh the token in the session, or fetch a new one if it cannot be
+/// refreshed, returning the new access token.
+pub fn renew(app: &App, session: &mut Option<Session>) -> HurlResult<String> {
+    let refresh_token = session
+        .as_ref()
+        .and_then(|s| s.oauth_token())
+        .and_then(|t| t.refresh_token.clone());
+    let token = match refresh_token {
+        Some(refresh_token) => match refresh(app, &refresh_token) {
+            Ok(token) => token,
+            Err(e) => {
+                debug!("Could not refresh the OAuth2 token: {}", e);
+                fetch(app)?
+            }
+        },
+        None => fetch(app)?,
+    };
+    let access_token = token.access_token.clone();
+    if let Some(s) = session {
+        s.set_oauth_token(token);
+    }
+    Ok(access_token)
+}
+
+fn grant(app: &App) -> HurlResult<Grant> {
+    match app.oauth_grant.as_deref() {
+        None | Some("client-credentials") => Ok(Grant::ClientCredentials),
+        Some("password") => Ok(Grant::Password),
+        Some(other) => Err(Error::InvalidOAuthGrant(other.to_owned())),
+    }
+}
+
+fn fetch(app: &App) -> HurlResult<Token> {
+    let mut form = Vec::new();
+    match grant(app)? {
+        Grant::ClientCredentials => form.push(("grant_type", "client_credentials".to_owned())),
+        Grant::Password => {
+            let auth = app.auth.as_ref().ok_or(Error::OAuthPasswordMissing)?;
+            let mut parts = auth.splitn(2, ':');
+            let username = parts.next().unwrap_or("").to_owned();
+            let password = parts.next().ok_or(Error::OAuthPasswordMissing)?.to_owned();
+            form.push(("grant_type", "password".to_owned()));
+            form.push(("username", username));
+            form.push(("password", password));
+        }
+    }
+    if let Some(scope) = &app.oauth_scope {
+        form.push(("scope", scope.clone()));
+    }
+    debug!("Fetching an OAuth2 token");
+    request_token(app, form)
+}
+
+fn refresh(app: &App, refresh_token: &str) -> HurlResult<Token> {
+    debug!("Refreshing the OAuth2 token");
+    let form = vec![
+        ("grant_type", "refresh_token".to_owned()),
+        ("refresh_token", refresh_token.to_owned()),
+    ];
+    let mut token = request_token(app, form)?;
+    // The server may keep the refresh token the same without sending it.
+    if token.refresh_token.is_none() {
+        token.refresh_token = Some(refresh_token.to_owned());
+    }
+    Ok(token)
+}
+
+/// Post a grant to the token endpoint. The client authenticates with basic
+/// authentication when it has a secret, and otherwise by its id alone.
+fn request_token(app: &App, mut form: Vec<(&str, String)>) -> HurlResult<Token> {
+    let token_url = app.oauth_token_url.as_deref().unwrap_or("");
+    let url = client::parse(app, token_url)?;
+    let client = client::build_client(app, &Default::default())?;
+    let mut builder = client.post(url);
+    match (&app.oauth_client_id, &app.oauth_client_secret) {
+        (Some(id), Some(secret)) => builder = builder.basic_auth(id, Some(secret)),
+        (Some(id), None) => form.push(("client_id", id.clone())),
+        (None, _) => {}
+    }
+    let mut resp = builder.form(&form).send()?;
+    let status = resp.status();
+    let body = resp.text()?;
+    if !status.is_success() {
+        let reason = match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(e) => match e.error_description {
+                Some(description) => format!("{}: {}", e.error, description),
+                None => e.error,
+            },
+            Err(_) => status.to_string(),
+        };
+        return Err(Error::OAuth(reason));
+    }
+    let resp: TokenResponse = serde_json::from_str(&body)?;
+    Ok(Token {
+        access_token: resp.access_token,
+        refresh_token: resp.refresh_token,
+        expires_at: resp.expires_in.map(|secs| cookies::now() + secs),
+    })
+}
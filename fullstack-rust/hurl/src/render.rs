@@ -0,0 +1,479 @@
+use crate::app::App;
+use crate::errors::{Error, HurlResult};
+use crate::filter::Filter;
+use log::trace;
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use serde_json::{json, Map, Value};
+use std::io::{self, Write};
+use syntect::highlighting::Theme;
+use syntect::parsing::SyntaxSet;
+
+/// The most bytes of a binary body to show as a hex dump in a terminal.
+const HEXDUMP_LIMIT: usize = 4096;
+
+const HTML_VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Types under `application/` which are known to be binary. Others are
+/// shown as text unless the body itself looks binary.
+const BINARY_APPLICATION_TYPES: &[&str] = &[
+    "application/octet-stream",
+    "application/pdf",
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-bzip2",
+    "application/x-xz",
+    "application/zstd",
+    "application/x-tar",
+    "application/x-7z-compressed",
+    "application/vnd.rar",
+    "application/x-rar-compressed",
+    "application/java-archive",
+    "application/wasm",
+    "application/msword",
+    "application/vnd.ms-excel",
+    "application/vnd.ms-powerpoint",
+    "application/x-protobuf",
+    "application/protobuf",
+    "application/grpc",
+    "application/msgpack",
+    "application/x-msgpack",
+    "application/cbor",
+    "application/x-executable",
+    "application/x-sharedlib",
+];
+
+const HTML_RAW_ELEMENTS: &[&str] = &["script", "style", "pre", "textarea"];
+
+/// How a response body is displayed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Json,
+    Xml,
+    Html,
+    Text,
+    Binary,
+}
+
+impl Kind {
+    /// Decide how to display `body` from the Content-Type header, looking
+    /// at the body itself when the header is missing or inconclusive.
+    pub fn detect(headers: &HeaderMap, body: &[u8]) -> Kind {
+        let mime = headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(';').next().unwrap_or("").trim().to_ascii_lowercase());
+        trace!("Response content type: {:?}", mime);
+
+        let kind = match mime {
+            Some(ref m) if m.ends_with("/json") || m.ends_with("+json") => Kind::Json,
+            Some(ref m) if m == "text/html" || m == "application/xhtml+xml" => Kind::Html,
+            Some(ref m) if m.ends_with("/xml") || m.ends_with("+xml") => Kind::Xml,
+            Some(ref m)
+                if m.starts_with("image/")
+                    || m.starts_with("audio/")
+                    || m.starts_with("video/")
+                    || m.starts_with("font/")
+                    || is_binary_application(m) =>
+            {
+                Kind::Binary
+            }
+            _ => Kind::Text,
+        };
+        if kind != Kind::Binary && looks_binary(body) {
+            Kind::Binary
+        } else {
+            kind
+        }
+    }
+}
+
+fn is_binary_application(mime: &str) -> bool {
+    BINARY_APPLICATION_TYPES.contains(&mime)
+        || mime.starts_with("application/vnd.openxmlformats-officedocument.")
+        || mime.starts_with("application/vnd.oasis.opendocument.")
+}
+
+/// Treat a body as binary if it is not UTF-8 or contains NUL bytes.
+fn looks_binary(body: &[u8]) -> bool {
+    body.contains(&0) || std::str::from_utf8(body).is_err()
+}
+
+/// Print a response body in the way that suits its kind.
+pub fn body(app: &App, ss: &SyntaxSet, theme: &Theme, kind: Kind, body: &[u8]) -> HurlResult<()> {
+    match kind {
+        Kind::Binary => binary(app, body),
+        Kind::Xml | Kind::Html => {
+            let text = String::from_utf8_lossy(body);
+            let syntax = if kind == Kind::Html { "HTML" } else { "XML" };
+            let pretty = indent_markup(&text, kind == Kind::Html);
+            crate::highlight_string(app, ss, theme, syntax, &pretty);
+            Ok(())
+        }
+        Kind::Json | Kind::Text => {
+            let text = String::from_utf8_lossy(body);
+            let result_json: serde_json::Result<crate::OrderedJson> = serde_json::from_str(&text);
+            match result_json {
+                Ok(result_value) => {
+                    let result_str = serde_json::to_string_pretty(&result_value)?;
+                    crate::highlight_string(app, ss, theme, "JSON", &result_str);
+                }
+                Err(e) => {
+                    trace!("Failed to parse result to JSON: {}", e);
+                    println!("{}", text);
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Print the values picked from a JSON body by --filter, one after the
+/// other, highlighted unless --raw-output is given.
+pub fn filtered(
+    app: &App,
+    ss: &SyntaxSet,
+    theme: &Theme,
+    filter: &Filter,
+    body: &[u8],
+) -> HurlResult<()> {
+    let value: Value = serde_json::from_slice(body).map_err(|_| Error::FilterNotJson)?;
+    for value in filter.apply(&value)? {
+        match value {
+            Value::String(s) if app.raw_output => println!("{}", s),
+            value => {
+                let text = serde_json::to_string_pretty(&value)?;
+                if app.raw_output {
+                    println!("{}", text);
+                } else {
+                    crate::highlight_string(app, ss, theme, "JSON", &text);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print the response as one JSON document for --output-format json, on
+/// one line when several requests are sent.
+///
+/// The body is kept as JSON when it parses, else given as a string, or as
+/// base64 when it is not UTF-8, with `body_encoding` saying which. It is
+/// null when the body was downloaded.
+pub fn exchange(
+    app: &App,
+    ss: &SyntaxSet,
+    theme: &Theme,
+    exchange: &crate::Exchange,
+    body: Option<&[u8]>,
+) -> HurlResult<()> {
+    let resp = &exchange.resp;
+    let mut headers = Map::new();
+    for (key, value) in resp.headers().iter() {
+        let value = Value::String(String::from_utf8_lossy(value.as_bytes()).into_owned());
+        match headers.get_mut(key.as_str()) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(first) => *first = Value::Array(vec![first.take(), value]),
+            None => {
+                headers.insert(key.as_str().to_owned(), value);
+            }
+        }
+    }
+    let (body, encoding) = match body {
+        None => (Value::Null, Value::Null),
+        Some(body) => match serde_json::from_slice::<Value>(body) {
+            Ok(value) => (value, "json".into()),
+            Err(_) => match std::str::from_utf8(body) {
+                Ok(text) => (text.into(), "text".into()),
+                Err(_) => (base64::encode(body).into(), "base64".into()),
+            },
+        },
+    };
+    let document = json!({
+        "status": resp.status().as_u16(),
+        "version": format!("{:?}", resp.version()),
+        "url": resp.url().as_str(),
+        "headers": headers,
+        "body": body,
+        "body_encoding": encoding,
+        "timing": exchange.timing.as_json(),
+        "redirects": exchange.redirects,
+    });
+    let text = if app.json_lines() {
+        serde_json::to_string(&document)?
+    } else {
+        serde_json::to_string_pretty(&document)?
+    };
+    crate::highlight_string(app, ss, theme, "JSON", &text);
+    Ok(())
+}
+
+/// Write binary data as it is when stdout is redirected, but only as a hex
+/// dump when it is a terminal unless --force-binary is given.
+fn binary(app: &App, body: &[u8]) -> HurlResult<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if app.force_binary || !atty::is(atty::Stream::Stdout) {
+        out.write_all(body)?;
+        out.flush()?;
+        return Ok(());
+    }
+    let shown = body.len().min(HEXDUMP_LIMIT);
+    hexdump(&mut out, &body[..shown])?;
+    if shown < body.len() {
+        writeln!(
+            out,
+            "... {} more bytes not shown, use --download to save the body",
+            body.len() - shown
+        )?;
+    }
+    Ok(())
+}
+
+/// Write `data` in the format of `hexdump -C`.
+pub fn hexdump<W: Write>(out: &mut W, data: &[u8]) -> io::Result<()> {
+    for (line, chunk) in data.chunks(16).enumerate() {
+        let mut hex = String::with_capacity(49);
+        for (i, byte) in chunk.iter().enumerate() {
+            if i == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", byte));
+        }
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(out, "{:08x}  {:<49} |{}|", line * 16, hex, ascii)?;
+    }
+    writeln!(out, "{:08x}", data.len())
+}
+
+enum Token<'a> {
+    Open(String, &'a str, bool),
+    Close(String, &'a str),
+    Markup(&'a str),
+    Text(&'a str),
+    Raw(&'a str),
+}
+
+/// Split markup into tags and the text between them.
+fn tokenize(src: &str, html: bool) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = src;
+    while !rest.is_empty() {
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            tokens.push(Token::Text(&rest[..end]));
+            rest = &rest[end..];
+            continue;
+        }
+
+        let terminator = if rest.starts_with("<!--") {
+            "-->"
+        } else if rest.starts_with("<![CDATA[") {
+            "]]>"
+        } else if rest.starts_with("<?") {
+            "?>"
+        } else {
+            ">"
+        };
+        let end = if terminator == ">" {
+            tag_end(rest)
+        } else {
+            rest.find(terminator).map(|i| i + terminator.len())
+        };
+        let end = match end {
+            Some(end) => end,
+            None => {
+                tokens.push(Token::Text(rest));
+                break;
+            }
+        };
+        let tag = &rest[..end];
+        rest = &rest[end..];
+
+        if terminator != ">" || tag.starts_with("<!") {
+            tokens.push(Token::Markup(tag));
+        } else if let Some(name) = tag.strip_prefix("</") {
+            tokens.push(Token::Close(tag_name(name, html), tag));
+        } else if tag[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            let name = tag_name(&tag[1..], html);
+            let self_closing =
+                tag.ends_with("/>") || (html && HTML_VOID_ELEMENTS.contains(&name.as_str()));
+            let raw = html && !self_closing && HTML_RAW_ELEMENTS.contains(&name.as_str());
+            tokens.push(Token::Open(name.clone(), tag, self_closing));
+            if raw {
+                let close = format!("</{}", name);
+                let end = rest.to_ascii_lowercase().find(&close).unwrap_or(rest.len());
+                if end > 0 {
+                    tokens.push(Token::Raw(&rest[..end]));
+                }
+                rest = &rest[end..];
+            }
+        } else {
+            tokens.push(Token::Text(tag));
+        }
+    }
+    tokens
+}
+
+/// Find the end of a tag starting at the beginning of `src`, skipping over
+/// `>` inside quoted attribute values.
+fn tag_end(src: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in src.char_indices().skip(1) {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '>') => return Some(i + 1),
+            (None, '<') => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn tag_name(src: &str, html: bool) -> String {
+    let end = src
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(src.len());
+    if html {
+        src[..end].to_ascii_lowercase()
+    } else {
+        src[..end].to_owned()
+    }
+}
+
+/// Put each element of an XML or HTML document on its own line, indented
+/// by its depth. Elements which only contain text stay on one line.
+///
+/// Text which does not look like markup is returned unchanged.
+pub fn indent_markup(src: &str, html: bool) -> String {
+    if !src.trim_start().starts_with('<') {
+        return src.to_owned();
+    }
+    let tokens = tokenize(src, html);
+    let mut out = String::with_capacity(src.len());
+    let mut open: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let indent = "  ".repeat(open.len());
+        match &tokens[i] {
+            Token::Open(name, tag, self_closing) => {
+                if *self_closing {
+                    out.push_str(&format!("{}{}\n", indent, tag));
+                } else if let Some((text, close, skip)) = inline_element(&tokens[i + 1..], name) {
+                    out.push_str(&format!("{}{}{}{}\n", indent, tag, text, close));
+                    i += skip;
+                } else {
+                    out.push_str(&format!("{}{}\n", indent, tag));
+                    open.push(name.clone());
+                }
+            }
+            Token::Close(name, tag) => {
+                if let Some(pos) = open.iter().rposition(|n| n == name) {
+                    open.truncate(pos);
+                }
+                out.push_str(&format!("{}{}\n", "  ".repeat(open.len()), tag));
+            }
+            Token::Markup(tag) => out.push_str(&format!("{}{}\n", indent, tag)),
+            Token::Text(text) => {
+                for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                    out.push_str(&format!("{}{}\n", indent, line));
+                }
+            }
+            Token::Raw(text) => {
+                let text = text.trim_matches(|c| c == '\n' || c == '\r');
+                if !text.trim().is_empty() {
+                    out.push_str(text);
+                    out.push('\n');
+                }
+            }
+        }
+        i += 1;
+    }
+    out
+}
+
+/// If `tokens` start with optional text followed by the closing tag for
+/// `name`, return the trimmed text, the closing tag and how many tokens
+/// they take up.
+fn inline_element<'a>(tokens: &[Token<'a>], name: &str) -> Option<(&'a str, &'a str, usize)> {
+    match tokens {
+        [Token::Close(close, tag), ..] if close == name => Some(("", tag, 1)),
+        [Token::Text(text), Token::Close(close, tag), ..] | [Token::Raw(text), Token::Close(close, tag), ..]
+            if close == name && !text.contains('\n') =>
+        {
+            Some((text.trim(), tag, 2))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn detect(content_type: Option<&'static str>, body: &[u8]) -> Kind {
+        let mut headers = HeaderMap::new();
+        if let Some(content_type) = content_type {
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        }
+        Kind::detect(&headers, body)
+    }
+
+    #[test]
+    fn detects_kinds_from_the_content_type() {
+        let json = Some("application/problem+json; charset=utf-8");
+        assert_eq!(detect(json, b"{}"), Kind::Json);
+        assert_eq!(detect(Some("text/html"), b"<p>"), Kind::Html);
+        assert_eq!(detect(Some("application/atom+xml"), b"<feed/>"), Kind::Xml);
+        assert_eq!(detect(Some("text/plain"), b"hi"), Kind::Text);
+        assert_eq!(detect(None, b"hi"), Kind::Text);
+    }
+
+    #[test]
+    fn shows_unknown_application_types_as_text() {
+        for content_type in &[
+            "application/x-ndjson",
+            "application/jwt",
+            "application/csv",
+            "application/vnd.example+yaml",
+        ] {
+            let kind = detect(Some(content_type), "a,b\ncaf\u{e9},2\n".as_bytes());
+            assert_eq!(kind, Kind::Text, "{}", content_type);
+        }
+        assert_eq!(
+            detect(Some("application/x-unknown"), b"\x00\x01"),
+            Kind::Binary
+        );
+    }
+
+    #[test]
+    fn keeps_known_binary_types_binary() {
+        assert_eq!(
+            detect(Some("application/octet-stream"), b"text"),
+            Kind::Binary
+        );
+        assert_eq!(detect(Some("application/pdf"), b"%PDF-1.4"), Kind::Binary);
+        assert_eq!(detect(Some("image/png"), b"text"), Kind::Binary);
+        let xlsx = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+        assert_eq!(detect(Some(xlsx), b"PK"), Kind::Binary);
+        assert_eq!(
+            detect(Some("application/json"), b"{\"a\":\x00}"),
+            Kind::Binary
+        );
+    }
+}
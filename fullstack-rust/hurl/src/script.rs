@@ -0,0 +1,480 @@
+use crate::app::{App, Parameter, RunData};
+use crate::assertions::{self, Assertion};
+use crate::body::RawBody;
+use crate::client;
+use crate::errors::{Error, HurlResult};
+use crate::jsonpath;
+use crate::session::{self, Session};
+use log::{debug, trace};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use syntect::highlighting::Theme;
+use syntect::parsing::SyntaxSet;
+
+/// Where the value for a captured variable comes from.
+#[derive(Debug)]
+pub enum Capture {
+    Body(String),
+    Header(String),
+    Status,
+}
+
+#[derive(Debug, Default)]
+pub struct Request {
+    pub name: Option<String>,
+    pub line: usize,
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    pub captures: Vec<(String, Capture)>,
+    pub expectations: Vec<Assertion>,
+}
+
+#[derive(Debug, Default)]
+pub struct RequestFile {
+    pub variables: Vec<(String, String)>,
+    pub requests: Vec<Request>,
+}
+
+enum State {
+    Preamble,
+    Headers,
+    Body,
+}
+
+pub fn parse(content: &str) -> HurlResult<RequestFile> {
+    let mut file = RequestFile::default();
+    let mut pending = Request::default();
+    let mut body: Vec<&str> = Vec::new();
+    let mut state = State::Preamble;
+
+    for (idx, line) in content.lines().enumerate() {
+        let line_no = idx + 1;
+        let trimmed = line.trim();
+
+        if trimmed.starts_with("###") {
+            finish(&mut file, &mut pending, &mut body, &state);
+            state = State::Preamble;
+            let title = trimmed.trim_start_matches('#').trim();
+            if !title.is_empty() {
+                pending.name = Some(title.to_owned());
+            }
+            continue;
+        }
+
+        match state {
+            State::Preamble => {
+                if trimmed.is_empty() {
+                    continue;
+                }
+                if let Some(comment) = strip_comment(trimmed) {
+                    parse_directive(comment, line_no, &mut pending)?;
+                } else if let Some(definition) = trimmed.strip_prefix('@') {
+                    let mut parts = definition.splitn(2, '=');
+                    match (parts.next(), parts.next()) {
+                        (Some(name), Some(value)) if !name.trim().is_empty() => file
+                            .variables
+                            .push((name.trim().to_owned(), value.trim().to_owned())),
+                        _ => {
+                            return Err(Error::RequestFileSyntax(
+                                line_no,
+                                "expected a variable of the form `@name = value`".to_owned(),
+                            ))
+                        }
+                    }
+                } else {
+                    parse_request_line(trimmed, line_no, &mut pending)?;
+                    state = State::Headers;
+                }
+            }
+            State::Headers => {
+                if trimmed.is_empty() {
+                    state = State::Body;
+                } else if let Some(comment) = strip_comment(trimmed) {
+                    if comment.starts_with('@') {
+                        return Err(Error::RequestFileSyntax(
+                            line_no,
+                            "directives must come before the request line".to_owned(),
+                        ));
+                    }
+                } else if let Some(idx) = trimmed.find(':') {
+                    let (key, value) = trimmed.split_at(idx);
+                    pending
+                        .headers
+                        .push((key.trim().to_owned(), value[1..].trim().to_owned()));
+                } else {
+                    return Err(Error::RequestFileSyntax(
+                        line_no,
+                        "expected a header of the form `key: value`".to_owned(),
+                    ));
+                }
+            }
+            State::Body => body.push(line),
+        }
+    }
+    finish(&mut file, &mut pending, &mut body, &state);
+
+    Ok(file)
+}
+
+fn finish(file: &mut RequestFile, pending: &mut Request, body: &mut Vec<&str>, state: &State) {
+    if let State::Preamble = state {
+        *pending = Request::default();
+        return;
+    }
+    while body.last().map(|l| l.trim().is_empty()).unwrap_or(false) {
+        body.pop();
+    }
+    let mut request = std::mem::take(pending);
+    if !body.is_empty() {
+        request.body = Some(body.join("\n"));
+    }
+    body.clear();
+    trace!("Parsed request: {:?}", request);
+    file.requests.push(request);
+}
+
+fn strip_comment(line: &str) -> Option<&str> {
+    if line.starts_with('#') {
+        Some(line.trim_start_matches('#').trim())
+    } else if line.starts_with("//") {
+        Some(line.trim_start_matches('/').trim())
+    } else {
+        None
+    }
+}
+
+fn parse_directive(comment: &str, line_no: usize, request: &mut Request) -> HurlResult<()> {
+    if !comment.starts_with('@') {
+        return Ok(());
+    }
+    let mut parts = comment[1..].splitn(2, char::is_whitespace);
+    let directive = parts.next().unwrap_or("");
+    let rest = parts.next().unwrap_or("").trim();
+    match directive {
+        "name" if !rest.is_empty() => {
+            request.name = Some(rest.to_owned());
+            Ok(())
+        }
+        "capture" => {
+            let mut parts = rest.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(source)) if !name.trim().is_empty() => {
+                    let capture = parse_capture(source.trim(), line_no)?;
+                    request.captures.push((name.trim().to_owned(), capture));
+                    Ok(())
+                }
+                _ => Err(Error::RequestFileSyntax(
+                    line_no,
+                    "expected a capture of the form `@capture name = source`".to_owned(),
+                )),
+            }
+        }
+        "expect" => {
+            request.expectations.push(parse_expectation(rest, line_no)?);
+            Ok(())
+        }
+        _ => Err(Error::RequestFileSyntax(
+            line_no,
+            format!("unknown directive `@{}`", directive),
+        )),
+    }
+}
+
+fn parse_capture(source: &str, line_no: usize) -> HurlResult<Capture> {
+    if source == "status" {
+        return Ok(Capture::Status);
+    }
+    if let Some(name) = source.strip_prefix("header ") {
+        return Ok(Capture::Header(name.trim().to_owned()));
+    }
+    let path = source.strip_prefix("body ").map(str::trim).unwrap_or(source);
+    if path.starts_with('$') || path.starts_with('.') {
+        jsonpath::parse(path)?;
+        return Ok(Capture::Body(path.to_owned()));
+    }
+    Err(Error::RequestFileSyntax(
+        line_no,
+        format!("unknown capture source `{}`", source),
+    ))
+}
+
+fn parse_expectation(src: &str, line_no: usize) -> HurlResult<Assertion> {
+    let mut parts = src.splitn(2, char::is_whitespace);
+    let kind = parts.next().unwrap_or("");
+    let rest = parts.next().unwrap_or("").trim();
+    match kind {
+        "status" => assertions::parse_status(rest),
+        "header" => assertions::parse_header(rest),
+        "json" => assertions::parse_json(rest),
+        "body" => assertions::parse_body(rest),
+        _ => Err(Error::RequestFileSyntax(
+            line_no,
+            format!("unknown expectation `{}`", src),
+        )),
+    }
+}
+
+fn parse_request_line(line: &str, line_no: usize, request: &mut Request) -> HurlResult<()> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let (method, url) = match parts.len() {
+        1 => ("GET", parts[0]),
+        2 => (parts[0], parts[1]),
+        3 if parts[2].starts_with("HTTP/") => (parts[0], parts[1]),
+        _ => {
+            return Err(Error::RequestFileSyntax(
+                line_no,
+                "expected a request line of the form `METHOD url`".to_owned(),
+            ))
+        }
+    };
+    request.line = line_no;
+    request.method = method.to_owned();
+    request.url = url.to_owned();
+    Ok(())
+}
+
+/// Replace every `{{name}}` in `s` with the value of the variable `name`.
+pub fn substitute(s: &str, variables: &HashMap<String, String>) -> HurlResult<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                let value = variables
+                    .get(name)
+                    .ok_or_else(|| Error::UndefinedVariable(name.to_owned()))?;
+                out.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+pub fn run(app: &App, ss: &SyntaxSet, theme: &Theme, data: &RunData) -> HurlResult<()> {
+    let content =
+        fs::read_to_string(&data.file).map_err(|e| Error::File(data.file.clone(), e))?;
+    let file = parse(&content)?;
+
+    let mut variables: HashMap<String, String> = data.variables.iter().cloned().collect();
+    for (name, raw) in file.variables.iter() {
+        if data.variables.iter().any(|(n, _)| n == name) {
+            continue;
+        }
+        let value = substitute(raw, &variables)?;
+        variables.insert(name.clone(), value);
+    }
+
+    let mut sessions: HashMap<String, Session> = HashMap::new();
+    for (i, request) in file.requests.iter().enumerate() {
+        if i > 0 && !app.json_output() {
+            println!();
+        }
+        debug!(
+            "Running request {} from line {}",
+            request.name.as_ref().unwrap_or(&request.url),
+            request.line
+        );
+
+        let method_str = substitute(&request.method, &variables)?;
+        let method = reqwest::Method::from_bytes(method_str.as_bytes())
+            .map_err(|_| Error::InvalidMethod(method_str.clone()))?;
+        let url = substitute(&request.url, &variables)?;
+        let mut parameters = Vec::with_capacity(request.headers.len());
+        for (key, value) in request.headers.iter() {
+            parameters.push(Parameter::Header {
+                key: substitute(key, &variables)?,
+                value: substitute(value, &variables)?,
+            });
+        }
+        let body = match &request.body {
+            Some(b) => Some(substitute(b, &variables)?),
+            None => None,
+        };
+
+        let host = session::host_key(app, &url)?;
+        let mut session = match &app.session {
+            Some(name) => match sessions.remove(&host) {
+                Some(s) => Some(s),
+                None => Some(Session::get_or_create(
+                    app,
+                    name.clone(),
+                    host.clone(),
+                    &url,
+                )?),
+            },
+            None => None,
+        };
+
+        if app.export.is_none() && !app.json_output() {
+            println!("{} {}", method, url);
+        }
+        let prepared = client::prepare(
+            app,
+            method,
+            &mut session,
+            &url,
+            &parameters,
+            body.map(|b| RawBody::new(b.into_bytes())),
+        )?;
+        if let Some(mut exchange) = crate::send(app, ss, theme, prepared, &mut session)? {
+            let text = crate::handle_response(
+                app,
+                ss,
+                theme,
+                &mut exchange,
+                &mut session,
+                &request.expectations,
+            )?;
+
+            for (name, capture) in request.captures.iter() {
+                let value = captured_value(capture, &exchange.resp, &text)
+                    .ok_or_else(|| Error::CaptureFailed(name.clone()))?;
+                debug!("Captured {}={}", name, value);
+                variables.insert(name.clone(), value);
+            }
+        }
+
+        if let Some(s) = session {
+            sessions.insert(host, s);
+        }
+    }
+    Ok(())
+}
+
+fn captured_value(capture: &Capture, resp: &reqwest::Response, body: &str) -> Option<String> {
+    match capture {
+        Capture::Status => Some(resp.status().as_u16().to_string()),
+        Capture::Header(name) => resp
+            .headers()
+            .get(name.as_str())
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_owned()),
+        Capture::Body(path) => {
+            let value: Value = serde_json::from_str(body).ok()?;
+            match jsonpath::select(&value, path).ok()?? {
+                Value::String(s) => Some(s),
+                v => Some(v.to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syntax_error_line(content: &str) -> usize {
+        match parse(content) {
+            Err(Error::RequestFileSyntax(line, _)) => line,
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_requests_with_preamble_directives() {
+        let file = parse(
+            "@host = http://example.com\n\
+             \n\
+             ### First\n\
+             # @capture id = $.items[0].id\n\
+             // @capture type = header Content-Type\n\
+             # @expect status 2xx\n\
+             # a comment which is not a directive\n\
+             POST {{host}}/items HTTP/1.1\n\
+             Content-Type: application/json\n\
+             # a header comment\n\
+             \n\
+             {\"name\": \"a\"}\n\
+             \n\
+             ###\n\
+             # @name second\n\
+             {{host}}/items/{{id}}\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            file.variables,
+            vec![("host".to_owned(), "http://example.com".to_owned())]
+        );
+        assert_eq!(file.requests.len(), 2);
+        let first = &file.requests[0];
+        assert_eq!(first.name.as_deref(), Some("First"));
+        assert_eq!(first.line, 8);
+        assert_eq!(
+            (first.method.as_str(), first.url.as_str()),
+            ("POST", "{{host}}/items")
+        );
+        assert_eq!(
+            first.headers,
+            vec![("Content-Type".to_owned(), "application/json".to_owned())]
+        );
+        assert_eq!(first.body.as_deref(), Some("{\"name\": \"a\"}"));
+        match &first.captures[..] {
+            [(id, Capture::Body(path)), (kind, Capture::Header(header))] => {
+                assert_eq!((id.as_str(), path.as_str()), ("id", "$.items[0].id"));
+                assert_eq!((kind.as_str(), header.as_str()), ("type", "Content-Type"));
+            }
+            other => panic!("unexpected captures {:?}", other),
+        }
+        assert!(matches!(first.expectations[..], [Assertion::Status(_)]));
+
+        let second = &file.requests[1];
+        assert_eq!(second.name.as_deref(), Some("second"));
+        assert_eq!(
+            (second.method.as_str(), second.url.as_str()),
+            ("GET", "{{host}}/items/{{id}}")
+        );
+        assert!(second.body.is_none());
+    }
+
+    #[test]
+    fn rejects_directives_after_the_request_line() {
+        assert_eq!(syntax_error_line("GET http://a\n# @expect status 200\n"), 2);
+        assert_eq!(
+            syntax_error_line("GET http://a\nAccept: */*\n// @capture x = status\n"),
+            3
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert_eq!(syntax_error_line("@novalue\nGET http://a\n"), 1);
+        assert_eq!(syntax_error_line("# @bogus\nGET http://a\n"), 1);
+        assert_eq!(
+            syntax_error_line("# @capture x = cookie a\nGET http://a\n"),
+            1
+        );
+        assert_eq!(syntax_error_line("# @expect size 3\nGET http://a\n"), 1);
+        assert_eq!(syntax_error_line("GET http://a extra words\n"), 1);
+        assert_eq!(syntax_error_line("GET http://a\nnot a header\n"), 2);
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        let variables: HashMap<String, String> =
+            vec![("host".to_owned(), "example.com".to_owned())]
+                .into_iter()
+                .collect();
+        assert_eq!(
+            substitute("http://{{host}}/{{ host }}", &variables).unwrap(),
+            "http://example.com/example.com"
+        );
+        assert_eq!(substitute("{{host", &variables).unwrap(), "{{host");
+        assert!(matches!(
+            substitute("{{missing}}", &variables),
+            Err(Error::UndefinedVariable(ref name)) if name == "missing"
+        ));
+    }
+}
@@ -0,0 +1,152 @@
+use crate::app::App;
+use crate::credentials;
+use crate::errors::{Error, HurlResult};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use log::trace;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Prefix marking a value which was encrypted by a `SecretStore`.
+const PREFIX: &str = "enc:v1:";
+const ROUNDS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Encrypts and decrypts secrets with a key derived from a passphrase or
+/// the contents of a key file.
+///
+/// Encrypted values are strings of the form `enc:v1:<salt>:<nonce>:<data>`
+/// with each part base64 encoded, so they can be stored anywhere a plain
+/// secret could be, e.g. a session file or the config file.
+pub struct SecretStore {
+    secret: Vec<u8>,
+    salt: [u8; SALT_LEN],
+    keys: RefCell<HashMap<Vec<u8>, Key>>,
+}
+
+impl fmt::Debug for SecretStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretStore {{ .. }}")
+    }
+}
+
+impl SecretStore {
+    pub fn new(secret: Vec<u8>) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        SecretStore {
+            secret,
+            salt,
+            keys: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Build the store configured by `--key-file` or `--encrypt-secrets`.
+    ///
+    /// The passphrase is taken from HURL_PASSPHRASE if it is set and
+    /// otherwise prompted for.
+    pub fn for_app(app: &App) -> HurlResult<Option<Self>> {
+        if let Some(path) = &app.key_file {
+            trace!("Using key file {}", path.display());
+            let secret = std::fs::read(path).map_err(|e| Error::File(path.clone(), e))?;
+            return Ok(Some(SecretStore::new(secret)));
+        }
+        if app.encrypt_secrets {
+            let passphrase = match std::env::var("HURL_PASSPHRASE") {
+                Ok(p) => p,
+                Err(_) => credentials::read_password("Passphrase: ")?
+                    .ok_or(Error::NoTerminal("passphrase"))?,
+            };
+            return Ok(Some(SecretStore::new(passphrase.into_bytes())));
+        }
+        Ok(None)
+    }
+
+    fn key(&self, salt: &[u8]) -> Key {
+        let mut keys = self.keys.borrow_mut();
+        *keys.entry(salt.to_vec()).or_insert_with(|| {
+            let mut key = Key::default();
+            pbkdf2::pbkdf2_hmac::<sha2::Sha256>(&self.secret, salt, ROUNDS, key.as_mut_slice());
+            key
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> HurlResult<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let cipher = ChaCha20Poly1305::new(&self.key(&self.salt));
+        let data = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+            .map_err(|_| Error::SecretEncryption)?;
+        Ok(format!(
+            "{}{}:{}:{}",
+            PREFIX,
+            base64::encode(self.salt),
+            base64::encode(nonce),
+            base64::encode(&data)
+        ))
+    }
+
+    pub fn decrypt(&self, value: &str) -> HurlResult<String> {
+        let parts: Vec<&str> = value.trim_start_matches(PREFIX).split(':').collect();
+        if parts.len() != 3 {
+            return Err(Error::SecretDecryption);
+        }
+        let decode = |s: &str| base64::decode(s).map_err(|_| Error::SecretDecryption);
+        let salt = decode(parts[0])?;
+        let nonce = decode(parts[1])?;
+        let data = decode(parts[2])?;
+        if nonce.len() != NONCE_LEN {
+            return Err(Error::SecretDecryption);
+        }
+        let cipher = ChaCha20Poly1305::new(&self.key(&salt));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), data.as_ref())
+            .map_err(|_| Error::SecretDecryption)?;
+        String::from_utf8(plaintext).map_err(|_| Error::SecretDecryption)
+    }
+}
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// Decrypt `value` if it is encrypted, otherwise return it unchanged.
+pub fn reveal(store: Option<&SecretStore>, value: &str) -> HurlResult<String> {
+    if !is_encrypted(value) {
+        return Ok(value.to_owned());
+    }
+    match store {
+        Some(store) => store.decrypt(value),
+        None => Err(Error::SecretLocked),
+    }
+}
+
+/// Encrypt `value` when there is a store, otherwise return it unchanged.
+pub fn conceal(store: Option<&SecretStore>, value: &str) -> HurlResult<String> {
+    match store {
+        Some(store) if !is_encrypted(value) => store.encrypt(value),
+        _ => Ok(value.to_owned()),
+    }
+}
+
+/// Hide a secret for display unless `show` is set.
+pub fn mask(value: &str, show: bool) -> String {
+    if show {
+        value.to_owned()
+    } else {
+        "********".to_owned()
+    }
+}
+
+/// Hide the password part of `username:password` unless `show` is set.
+pub fn mask_auth(auth: &str, show: bool) -> String {
+    match auth.find(':') {
+        Some(idx) if !show => format!("{}:{}", &auth[..idx], mask(&auth[idx + 1..], show)),
+        _ => mask(auth, show || !auth.contains(':')),
+    }
+}
@@ -1,23 +1,34 @@
-use crate::app::{App, Parameter};
+use crate::app::{App, Parameter, SessionCommand};
+use crate::auth::AuthType;
+use crate::client;
+use crate::cookies::{self, CookieJar, StoredCookie};
 use crate::directories::DIRECTORIES;
-use crate::errors::HurlResult;
+use crate::errors::{Error, HurlResult};
+use crate::oauth;
+use crate::secrets::{self, mask, mask_auth};
+use log::warn;
 use reqwest::header::COOKIE;
-use reqwest::RequestBuilder;
+use reqwest::{RequestBuilder, Url};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs::{create_dir_all, File, OpenOptions};
-use std::io::{BufReader, BufWriter};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Session {
     path: PathBuf,
     name: String,
     host: String,
     auth: Option<String>,
     token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auth_type: Option<AuthType>,
     headers: HashMap<String, String>,
-    cookies: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    oauth: Option<oauth::Token>,
+    #[serde(default, deserialize_with = "cookies::deserialize_jar")]
+    cookies: CookieJar,
 }
 
 impl Session {
@@ -31,18 +42,112 @@ impl Session {
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn oauth_token(&self) -> Option<&oauth::Token> {
+        self.oauth.as_ref()
+    }
+
+    pub fn set_oauth_token(&mut self, token: oauth::Token) {
+        self.oauth = Some(token);
+    }
+
     pub fn load(app: &App, name: &str, host: &str) -> HurlResult<Self> {
         let path = Session::path(app, name, host);
-        let file = File::open(path)?;
+        let mut session = Session::load_from(&path)?;
+        session.reveal(app)?;
+        session.adopt_legacy_cookies();
+        Ok(session)
+    }
+
+    fn load_from(path: &Path) -> HurlResult<Self> {
+        let file = File::open(path).map_err(|e| Error::File(path.to_owned(), e))?;
         let reader = BufReader::new(file);
         serde_json::from_reader(reader).map_err(|e| e.into())
     }
 
-    pub fn get_or_create(app: &App, name: String, host: String) -> Self {
-        match Session::load(app, &name, &host) {
-            Ok(session) => session,
-            Err(_) => Session::new(app, name, host),
+    /// Load a session as stored, leaving any encrypted secrets encrypted.
+    fn find(app: &App, name: &str, host: &str) -> HurlResult<Self> {
+        let path = Session::path(app, name, host);
+        if !path.is_file() {
+            return Err(Error::SessionNotFound(host.to_owned(), name.to_owned()));
         }
+        Session::load_from(&path)
+    }
+
+    /// Every stored session, sorted by host and then by name.
+    pub fn all(app: &App) -> HurlResult<Vec<Session>> {
+        let root = Session::root(app);
+        let mut sessions = Vec::new();
+        if !root.is_dir() {
+            return Ok(sessions);
+        }
+        for host_dir in fs::read_dir(root)? {
+            let host_dir = host_dir?.path();
+            if !host_dir.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&host_dir)? {
+                let path = entry?.path();
+                if path.extension().map(|e| e != "json").unwrap_or(true) {
+                    continue;
+                }
+                match Session::load_from(&path) {
+                    Ok(session) => sessions.push(session),
+                    Err(e) => warn!("Skipping session {}: {}", path.display(), e),
+                }
+            }
+        }
+        sessions.sort_by(|a, b| a.host.cmp(&b.host).then(a.name.cmp(&b.name)));
+        Ok(sessions)
+    }
+
+    /// Load the named session, or start a new one if none is stored.
+    ///
+    /// A stored session which cannot be read, e.g. because its secrets are
+    /// encrypted and no key was given, is an error rather than being replaced.
+    pub fn get_or_create(app: &App, name: String, host: String, url: &str) -> HurlResult<Self> {
+        Session::move_legacy(app, &name, &host, url)?;
+        if Session::path(app, &name, &host).is_file() {
+            Session::load(app, &name, &host)
+        } else {
+            Ok(Session::new(app, name, host))
+        }
+    }
+
+    /// Sessions used to be kept in a directory named after the whole URL of
+    /// the request. Move one found there to its host directory.
+    fn move_legacy(app: &App, name: &str, host: &str, url: &str) -> HurlResult<()> {
+        let path = Session::path(app, name, host);
+        let legacy_path = Session::path(app, name, url);
+        if path.is_file() || legacy_path == path || !legacy_path.is_file() {
+            return Ok(());
+        }
+        let mut session = Session::load_from(&legacy_path)?;
+        session.host = host.to_owned();
+        session.path = path;
+        session.adopt_legacy_cookies();
+        session.write(app)?;
+        fs::remove_file(&legacy_path)?;
+        // Only succeeds once the last session for the URL is gone.
+        let _ = fs::remove_dir(Session::dir(app, url));
+        Ok(())
+    }
+
+    /// Cookies from sessions saved before domains were recorded belong to
+    /// the host of the session, without its port.
+    fn adopt_legacy_cookies(&mut self) {
+        let host = match self.host.rfind(':') {
+            Some(idx) if !self.host.ends_with(']') => &self.host[..idx],
+            _ => &self.host,
+        };
+        self.cookies.adopt_legacy(host);
     }
 
     fn path(app: &App, name: &str, host: &str) -> PathBuf {
@@ -54,26 +159,97 @@ impl Session {
     }
 
     fn dir(app: &App, host: &str) -> PathBuf {
-        let mut session_dir = app
-            .session_dir
+        let mut session_dir = Session::root(app);
+        session_dir.push(make_safe_pathname(host));
+        session_dir
+    }
+
+    fn root(app: &App) -> PathBuf {
+        app.session_dir
             .as_ref()
             .cloned()
             .filter(|session_dir| session_dir.is_dir())
-            .unwrap_or_else(|| DIRECTORIES.config().join("sessions"));
-        session_dir.push(make_safe_pathname(host));
-        session_dir
+            .unwrap_or_else(|| DIRECTORIES.config().join("sessions"))
     }
 
+    /// Write the session to a temporary file which then replaces the stored
+    /// one, so that a failure part way leaves the old session intact.
     pub fn save(&self, app: &App) -> HurlResult<()> {
-        let dir = Session::dir(app, &self.host);
-        create_dir_all(dir)?;
-        let file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&self.path)?;
-        let writer = BufWriter::new(file);
-        serde_json::to_writer(writer, &self).map_err(|e| e.into())
+        self.for_storage(app)?.write(app)
+    }
+
+    /// Write the session exactly as it is, without encrypting or dropping
+    /// anything.
+    fn write(&self, app: &App) -> HurlResult<()> {
+        create_dir_all(Session::dir(app, &self.host))?;
+        write_private(&self.path, &serde_json::to_vec(self)?)
+    }
+
+    /// The session as it is written to disk. Secrets, including cookie
+    /// values, are encrypted when a key is available and left out entirely
+    /// with --no-store-secrets.
+    fn for_storage(&self, app: &App) -> HurlResult<Session> {
+        let mut stored = self.clone();
+        if app.no_store_secrets {
+            stored.auth = None;
+            stored.token = None;
+            stored.oauth = None;
+            stored.headers.retain(|key, _| !is_sensitive_header(key));
+            stored.cookies = CookieJar::default();
+            return Ok(stored);
+        }
+        let store = app.secrets.as_ref();
+        stored.auth = stored
+            .auth
+            .map(|auth| secrets::conceal(store, &auth))
+            .transpose()?;
+        stored.token = stored
+            .token
+            .map(|token| secrets::conceal(store, &token))
+            .transpose()?;
+        if let Some(oauth) = &mut stored.oauth {
+            oauth.access_token = secrets::conceal(store, &oauth.access_token)?;
+            oauth.refresh_token = oauth
+                .refresh_token
+                .as_ref()
+                .map(|token| secrets::conceal(store, token))
+                .transpose()?;
+        }
+        for (key, value) in stored.headers.iter_mut() {
+            if is_sensitive_header(key) {
+                *value = secrets::conceal(store, value)?;
+            }
+        }
+        for cookie in stored.cookies.iter_mut() {
+            cookie.value = secrets::conceal(store, &cookie.value)?;
+        }
+        Ok(stored)
+    }
+
+    /// Decrypt the secrets of a session loaded from disk.
+    fn reveal(&mut self, app: &App) -> HurlResult<()> {
+        let store = app.secrets.as_ref();
+        if let Some(auth) = &self.auth {
+            self.auth = Some(secrets::reveal(store, auth)?);
+        }
+        if let Some(token) = &self.token {
+            self.token = Some(secrets::reveal(store, token)?);
+        }
+        if let Some(oauth) = &mut self.oauth {
+            oauth.access_token = secrets::reveal(store, &oauth.access_token)?;
+            oauth.refresh_token = oauth
+                .refresh_token
+                .as_ref()
+                .map(|token| secrets::reveal(store, token))
+                .transpose()?;
+        }
+        for value in self.headers.values_mut() {
+            *value = secrets::reveal(store, value)?;
+        }
+        for cookie in self.cookies.iter_mut() {
+            cookie.value = secrets::reveal(store, &cookie.value)?;
+        }
+        Ok(())
     }
 
     pub fn update_with_parameters(&mut self, parameters: &Vec<Parameter>) {
@@ -91,7 +267,16 @@ impl Session {
         }
     }
 
-    pub fn update_auth(&mut self, auth: &Option<String>, token: &Option<String>) {
+    pub fn update_auth(
+        &mut self,
+        auth_type: Option<AuthType>,
+        auth: &Option<String>,
+        token: &Option<String>,
+    ) {
+        if auth_type.is_some() {
+            self.auth_type = auth_type;
+        }
+
         if auth.is_some() {
             self.auth = auth.clone();
         }
@@ -101,30 +286,313 @@ impl Session {
         }
     }
 
-    pub fn add_to_request(&self, mut builder: RequestBuilder) -> RequestBuilder {
+    pub fn add_to_request(&self, mut builder: RequestBuilder, url: &Url) -> RequestBuilder {
         for (key, value) in self.headers.iter() {
             builder = builder.header(key, value);
         }
-        let cookies = self
-            .cookies
-            .iter()
-            .map(|(name, value)| format!("{}={}", name, value))
-            .collect::<Vec<String>>()
-            .join("; ");
-        if cookies.is_empty() {
-            return builder;
+        match self.cookies.header_for(url) {
+            Some(cookies) => builder.header(COOKIE, cookies),
+            None => builder,
         }
-        builder.header(COOKIE, cookies)
     }
 
     pub fn update_with_response(&mut self, resp: &reqwest::Response) {
         for cookie in resp.cookies() {
-            self.cookies
-                .push((cookie.name().to_owned(), cookie.value().to_owned()));
+            self.cookies.store(&cookie, resp.url());
+        }
+        self.cookies.remove_expired();
+    }
+
+    fn print(&self, show_secrets: bool) {
+        println!("Session {} for {}", self.name, self.host);
+        if let Some(auth) = &self.auth {
+            println!("Auth: {}", mask_auth(auth, show_secrets));
+        }
+        if let Some(token) = &self.token {
+            println!("Token: {}", mask(token, show_secrets));
+        }
+        if let Some(auth_type) = self.auth_type {
+            println!("Auth type: {}", auth_type);
+        }
+        let now = cookies::now();
+        if let Some(oauth) = &self.oauth {
+            let expiry = match oauth.expires_at {
+                Some(at) if at > now => format!("expires in {}s", at - now),
+                Some(_) => "expired".to_owned(),
+                None => "no expiry".to_owned(),
+            };
+            let refresh = if oauth.refresh_token.is_some() {
+                ", can be refreshed"
+            } else {
+                ""
+            };
+            println!(
+                "OAuth2 token: {} ({}{})",
+                mask(&oauth.access_token, show_secrets),
+                expiry,
+                refresh
+            );
+        }
+        if !self.headers.is_empty() {
+            println!("Headers:");
+            let headers: BTreeMap<&String, &String> = self.headers.iter().collect();
+            for (key, value) in headers {
+                let value = mask(value, show_secrets || !is_sensitive_header(key));
+                println!("    {}: {}", key, value);
+            }
+        }
+        let mut printed_heading = false;
+        for cookie in self.cookies.iter() {
+            if !printed_heading {
+                println!("Cookies:");
+                printed_heading = true;
+            }
+            println!(
+                "    {}={} ({})",
+                cookie.name,
+                mask(&cookie.value, show_secrets),
+                describe_cookie(cookie, now)
+            );
+        }
+    }
+
+    /// A flat view of the session used to compare two sessions. Each entry
+    /// records whether its value is a secret which should not be printed.
+    fn entries(&self) -> BTreeMap<String, (String, bool)> {
+        let mut entries = BTreeMap::new();
+        if let Some(auth) = &self.auth {
+            entries.insert("auth".to_owned(), (auth.clone(), true));
+        }
+        if let Some(token) = &self.token {
+            entries.insert("token".to_owned(), (token.clone(), true));
+        }
+        if let Some(auth_type) = self.auth_type {
+            entries.insert("auth type".to_owned(), (auth_type.to_string(), false));
         }
+        if let Some(oauth) = &self.oauth {
+            entries.insert(
+                "oauth token".to_owned(),
+                (oauth.access_token.clone(), true),
+            );
+        }
+        for (key, value) in self.headers.iter() {
+            entries.insert(
+                format!("header {}", key),
+                (value.clone(), is_sensitive_header(key)),
+            );
+        }
+        for cookie in self.cookies.iter() {
+            entries.insert(
+                format!("cookie {} ({}{})", cookie.name, cookie.domain, cookie.path),
+                (cookie.value.clone(), true),
+            );
+        }
+        entries
     }
 }
 
+pub fn run_command(app: &App, cmd: &SessionCommand) -> HurlResult<()> {
+    match cmd {
+        SessionCommand::List { host } => {
+            let wanted = host.as_ref().map(|h| make_safe_pathname(h));
+            let mut current: Option<String> = None;
+            for session in Session::all(app)? {
+                let safe_host = make_safe_pathname(&session.host);
+                if wanted.as_ref().map(|w| *w != safe_host).unwrap_or(false) {
+                    continue;
+                }
+                if current.as_ref() != Some(&session.host) {
+                    println!("{}", session.host);
+                    current = Some(session.host.clone());
+                }
+                println!("    {}", session.name);
+            }
+            Ok(())
+        }
+        SessionCommand::Show {
+            host,
+            name,
+            show_secrets,
+        } => {
+            let mut session = Session::find(app, name, host)?;
+            if *show_secrets {
+                session.reveal(app)?;
+            }
+            session.print(*show_secrets);
+            Ok(())
+        }
+        SessionCommand::Diff {
+            host,
+            name,
+            other,
+            other_host,
+        } => {
+            let other_host = other_host.as_ref().unwrap_or(host);
+            let mut a = Session::find(app, name, host)?;
+            let mut b = Session::find(app, other, other_host)?;
+            a.reveal(app)?;
+            b.reveal(app)?;
+            let (a, b) = (a.entries(), b.entries());
+            println!("--- {} {}", host, name);
+            println!("+++ {} {}", other_host, other);
+            let keys: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                match (a.get(key), b.get(key)) {
+                    (Some((va, sa)), Some((vb, sb))) if va != vb => {
+                        if *sa || *sb {
+                            println!("~ {}: values differ", key);
+                        } else {
+                            println!("- {}: {}", key, va);
+                            println!("+ {}: {}", key, vb);
+                        }
+                    }
+                    (Some((va, sa)), None) => println!("- {}: {}", key, mask(va, !sa)),
+                    (None, Some((vb, sb))) => println!("+ {}: {}", key, mask(vb, !sb)),
+                    _ => {}
+                }
+            }
+            Ok(())
+        }
+        SessionCommand::Rm { host, name } => {
+            let path = Session::path(app, name, host);
+            if !path.is_file() {
+                return Err(Error::SessionNotFound(host.clone(), name.clone()));
+            }
+            fs::remove_file(&path)?;
+            // Only succeeds once the last session for the host is gone.
+            let _ = fs::remove_dir(Session::dir(app, host));
+            Ok(())
+        }
+        SessionCommand::Rename {
+            host,
+            name,
+            new_name,
+        } => {
+            let mut session = Session::find(app, name, host)?;
+            let new_path = Session::path(app, new_name, host);
+            if new_path.exists() {
+                return Err(Error::SessionExists(host.clone(), new_name.clone()));
+            }
+            session.name = new_name.clone();
+            session.path = new_path;
+            session.write(app)?;
+            fs::remove_file(Session::path(app, name, host))?;
+            Ok(())
+        }
+        SessionCommand::Export { host, name, output } => {
+            let session = Session::find(app, name, host)?;
+            let json = serde_json::to_string_pretty(&session)?;
+            match output {
+                Some(path) => fs::write(path, json)?,
+                None => println!("{}", json),
+            }
+            Ok(())
+        }
+        SessionCommand::Import {
+            file,
+            host,
+            name,
+            force,
+        } => {
+            let mut content = String::new();
+            if file.as_os_str() == "-" {
+                std::io::stdin().read_to_string(&mut content)?;
+            } else {
+                content =
+                    fs::read_to_string(file).map_err(|e| Error::File(file.clone(), e))?;
+            }
+            let mut session: Session = serde_json::from_str(&content)?;
+            if let Some(host) = host {
+                session.host = host.clone();
+            }
+            if let Some(name) = name {
+                session.name = name.clone();
+            }
+            session.path = Session::path(app, &session.name, &session.host);
+            if session.path.exists() && !force {
+                return Err(Error::SessionExists(session.host, session.name));
+            }
+            session.write(app)?;
+            println!("Imported session {} for {}", session.name, session.host);
+            Ok(())
+        }
+    }
+}
+
+pub fn is_sensitive_header(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    key == "authorization"
+        || key == "proxy-authorization"
+        || key == "cookie"
+        || ["token", "secret", "key", "password"]
+            .iter()
+            .any(|s| key.contains(s))
+}
+
+fn describe_cookie(cookie: &StoredCookie, now: u64) -> String {
+    let mut parts = Vec::new();
+    if cookie.domain.is_empty() {
+        parts.push("any host".to_owned());
+    } else if cookie.host_only {
+        parts.push(format!("host {}", cookie.domain));
+    } else {
+        parts.push(format!("domain {}", cookie.domain));
+    }
+    parts.push(format!("path {}", cookie.path));
+    match cookie.expires {
+        Some(at) => parts.push(format!("expires in {}s", at.saturating_sub(now))),
+        None => parts.push("session".to_owned()),
+    }
+    if cookie.secure {
+        parts.push("Secure".to_owned());
+    }
+    if cookie.http_only {
+        parts.push("HttpOnly".to_owned());
+    }
+    if let Some(same_site) = &cookie.same_site {
+        parts.push(format!("SameSite={}", same_site));
+    }
+    parts.join(", ")
+}
+
+/// The host a session for `url` is stored under, its host and port after
+/// resolving it against the base URL, so that every request to a server
+/// shares cookies and headers whichever way it was made.
+pub fn host_key(app: &App, url: &str) -> HurlResult<String> {
+    let url = client::parse(app, url)?;
+    let host = url.host_str().unwrap_or("");
+    Ok(match url.port_or_known_default() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_owned(),
+    })
+}
+
+/// Replace the file at `path` with `contents`, readable only by its owner.
+/// The contents go to a fresh `<path>.tmp` first, which is then renamed
+/// into place so a failed write never leaves a truncated file behind.
+pub fn write_private(path: &Path, contents: &[u8]) -> HurlResult<()> {
+    let mut tmp_path = path.to_owned().into_os_string();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    if tmp_path.exists() {
+        fs::remove_file(&tmp_path).map_err(|e| Error::FileWrite(tmp_path.clone(), e))?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&tmp_path)
+        .map_err(|e| Error::FileWrite(tmp_path.clone(), e))?;
+    file.write_all(contents)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| Error::FileWrite(tmp_path.clone(), e))?;
+    fs::rename(&tmp_path, path).map_err(|e| Error::FileWrite(path.to_owned(), e))
+}
+
 pub fn make_safe_pathname(s: &str) -> String {
     let mut buf = String::with_capacity(s.len());
     for c in s.chars() {
@@ -135,3 +603,58 @@ pub fn make_safe_pathname(s: &str) -> String {
     }
     buf
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_private_files_in_place() {
+        let dir = std::env::temp_dir().join(format!("hurl-session-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("s.json");
+        fs::write(dir.join("s.json.tmp"), "stale").unwrap();
+
+        write_private(&path, b"one").unwrap();
+        write_private(&path, b"two").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "two");
+        assert!(!dir.join("s.json.tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn moves_sessions_from_the_url_directory() {
+        use structopt::StructOpt;
+
+        let root = std::env::temp_dir().join(format!("hurl-sessions-{}", std::process::id()));
+        let url = "http://example.com/a?b=c";
+        let legacy_dir = root.join(make_safe_pathname(url));
+        create_dir_all(&legacy_dir).unwrap();
+        fs::write(
+            legacy_dir.join("old.json"),
+            r#"{"path":"","name":"old","host":"","auth":"u:p","token":null,"headers":{"X-Keep":"yes"},"cookies":[["a","1"]]}"#,
+        )
+        .unwrap();
+        let root_arg = root.to_str().unwrap();
+        let app = App::from_iter(&["hurl", "--session-dir", root_arg, url]);
+
+        let session = Session::get_or_create(&app, "old".into(), app.host().unwrap(), url).unwrap();
+        assert_eq!(session.host, "example.com:80");
+        assert_eq!(session.auth.as_deref(), Some("u:p"));
+        assert_eq!(session.headers["X-Keep"], "yes");
+        let cookie = session.cookies.iter().next().unwrap();
+        assert_eq!(
+            (cookie.domain.as_str(), cookie.host_only),
+            ("example.com", true)
+        );
+        assert!(root.join("example_com_80").join("old.json").is_file());
+        assert!(!legacy_dir.exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -14,6 +14,16 @@ pub fn build() -> HurlResult<(SyntaxSet, ThemeSet)> {
     let json_def = SyntaxDefinition::load_from_str(json_syntax_def, true, None)
         .map_err(|_| Error::SyntaxLoadError("JSON"))?;
     builder.add(json_def);
+
+    let xml_syntax_def = include_str!("../XML.sublime-syntax");
+    let xml_def = SyntaxDefinition::load_from_str(xml_syntax_def, true, None)
+        .map_err(|_| Error::SyntaxLoadError("XML"))?;
+    builder.add(xml_def);
+
+    let html_syntax_def = include_str!("../HTML.sublime-syntax");
+    let html_def = SyntaxDefinition::load_from_str(html_syntax_def, true, None)
+        .map_err(|_| Error::SyntaxLoadError("HTML"))?;
+    builder.add(html_def);
     let ss = builder.build();
 
     let ts = ThemeSet::load_defaults();
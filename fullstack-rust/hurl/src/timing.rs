@@ -0,0 +1,122 @@
+use crate::app::App;
+use log::debug;
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// How long each part of an exchange took.
+#[derive(Debug, Default, Clone)]
+pub struct Timing {
+    /// Estimates from a separate connection, see `probe`.
+    pub dns: Option<Duration>,
+    pub connect: Option<Duration>,
+    pub tls: Option<Duration>,
+    /// From sending the request until the response headers arrived,
+    /// including setting up the connection.
+    pub response: Duration,
+    pub transfer: Option<Duration>,
+}
+
+impl Timing {
+    pub fn total(&self) -> Duration {
+        self.response + self.transfer.unwrap_or_default()
+    }
+
+    /// Print the report for --timing to stderr.
+    pub fn print(&self) {
+        let rows = [
+            ("DNS lookup*", self.dns),
+            ("TCP connection*", self.connect),
+            ("TLS handshake*", self.tls),
+            ("Time to first byte", Some(self.response)),
+            ("Content transfer", self.transfer),
+            ("Total", Some(self.total())),
+        ];
+        eprintln!();
+        for (name, duration) in rows.iter() {
+            match duration {
+                Some(d) => eprintln!("{:<20}{:>10}", name, millis(*d)),
+                None => eprintln!("{:<20}{:>10}", name, "-"),
+            }
+        }
+        if self.dns.is_some() {
+            eprintln!("* estimated on a separate connection");
+        }
+    }
+
+    /// The report for --output-format json, in milliseconds, with null for
+    /// the steps which were not measured. The estimates from a separate
+    /// connection are kept apart from the times of the request itself.
+    pub fn as_json(&self) -> Value {
+        let ms = |d: Option<Duration>| d.map(|d| (d.as_secs_f64() * 1e6).round() / 1000.0);
+        json!({
+            "estimated": {
+                "dns": ms(self.dns),
+                "connect": ms(self.connect),
+                "tls": ms(self.tls),
+            },
+            "ttfb": ms(Some(self.response)),
+            "transfer": ms(self.transfer),
+            "total": ms(Some(self.total())),
+        })
+    }
+}
+
+/// Format a duration in milliseconds.
+pub fn millis(d: Duration) -> String {
+    format!("{:.1} ms", d.as_secs_f64() * 1000.0)
+}
+
+/// Estimate the DNS lookup, TCP connection and TLS handshake for `url` by
+/// timing them on a connection of its own, as the client does not report
+/// those of the request. Nothing is measured through a proxy, and a step
+/// which fails is left out.
+pub fn probe(app: &App, url: &Url) -> Timing {
+    let mut timing = Timing::default();
+    if app.proxy.is_some() {
+        return timing;
+    }
+    let (host, port) = match (url.host_str(), url.port_or_known_default()) {
+        (Some(host), Some(port)) => (host.trim_start_matches('[').trim_end_matches(']'), port),
+        _ => return timing,
+    };
+
+    let start = Instant::now();
+    let addr = match (host, port).to_socket_addrs().map(|mut a| a.next()) {
+        Ok(Some(addr)) => addr,
+        _ => {
+            debug!("Could not resolve {} for timing", host);
+            return timing;
+        }
+    };
+    timing.dns = Some(start.elapsed());
+
+    let start = Instant::now();
+    let timeout = app
+        .connect_timeout
+        .unwrap_or_else(|| Duration::from_secs(30));
+    let stream = match TcpStream::connect_timeout(&addr, timeout) {
+        Ok(stream) => stream,
+        Err(e) => {
+            debug!("Could not connect to {} for timing: {}", addr, e);
+            return timing;
+        }
+    };
+    timing.connect = Some(start.elapsed());
+
+    if url.scheme() == "https" {
+        // Only the time taken matters here, the certificate is checked by
+        // the request itself.
+        let connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .build();
+        let start = Instant::now();
+        match connector.map(|c| c.connect(host, stream)) {
+            Ok(Ok(_)) => timing.tls = Some(start.elapsed()),
+            _ => debug!("TLS handshake with {} failed during timing", host),
+        }
+    }
+    timing
+}
@@ -0,0 +1,112 @@
+use crate::errors::{Error, HurlResult};
+use log::trace;
+use reqwest::{Certificate, Identity};
+use std::fs;
+use std::path::Path;
+
+const PEM_CERTIFICATE_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_CERTIFICATE_END: &str = "-----END CERTIFICATE-----";
+
+/// Read the CA certificates from a PEM bundle or a single DER certificate.
+pub fn ca_certificates(path: &Path) -> HurlResult<Vec<Certificate>> {
+    let error = |msg: String| Error::Certificate(path.to_owned(), msg);
+    let data = fs::read(path).map_err(|e| Error::File(path.to_owned(), e))?;
+    if !is_pem(&data) {
+        let cert = Certificate::from_der(&data)
+            .map_err(|_| error("not a PEM or DER certificate".to_owned()))?;
+        return Ok(vec![cert]);
+    }
+
+    let text = String::from_utf8_lossy(&data);
+    let mut certs = Vec::new();
+    let mut rest = text.as_ref();
+    while let Some(start) = rest.find(PEM_CERTIFICATE_BEGIN) {
+        let end = rest[start..]
+            .find(PEM_CERTIFICATE_END)
+            .map(|i| start + i + PEM_CERTIFICATE_END.len())
+            .ok_or_else(|| error("unterminated PEM certificate".to_owned()))?;
+        let cert = Certificate::from_pem(&rest.as_bytes()[start..end])
+            .map_err(|e| error(e.to_string()))?;
+        certs.push(cert);
+        rest = &rest[end..];
+    }
+    if certs.is_empty() {
+        return Err(error("no certificates found".to_owned()));
+    }
+    trace!(
+        "Loaded {} CA certificate(s) from {}",
+        certs.len(),
+        path.display()
+    );
+    Ok(certs)
+}
+
+/// Load a client certificate for mutual TLS.
+///
+/// A `.p12` or `.pfx` file is read as PKCS#12 with the password from
+/// HURL_CERT_PASSWORD. Anything else is read as a PEM certificate chain,
+/// with the private key taken from `key` or else from the same file.
+pub fn identity(cert: &Path, key: Option<&Path>) -> HurlResult<Identity> {
+    let error = |msg: String| Error::Certificate(cert.to_owned(), msg);
+    let data = fs::read(cert).map_err(|e| Error::File(cert.to_owned(), e))?;
+
+    if is_pkcs12(cert) {
+        if key.is_some() {
+            return Err(error("--key cannot be used with a PKCS#12 file".to_owned()));
+        }
+        let password = std::env::var("HURL_CERT_PASSWORD").unwrap_or_default();
+        return Identity::from_pkcs12_der(&data, &password).map_err(|e| error(e.to_string()));
+    }
+
+    let key_data = match key {
+        Some(path) => fs::read(path).map_err(|e| Error::File(path.to_owned(), e))?,
+        None => data.clone(),
+    };
+    let der = pem_to_pkcs12(&data, &key_data).map_err(error)?;
+    Identity::from_pkcs12_der(&der, "").map_err(|e| error(e.to_string()))
+}
+
+fn is_pem(data: &[u8]) -> bool {
+    String::from_utf8_lossy(data).contains("-----BEGIN ")
+}
+
+pub fn is_pkcs12(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("p12") || ext.eq_ignore_ascii_case("pfx"),
+        None => false,
+    }
+}
+
+/// Bundle a PEM certificate chain and private key as PKCS#12, which is the
+/// only form of client identity the native TLS backend accepts.
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+fn pem_to_pkcs12(cert: &[u8], key: &[u8]) -> Result<Vec<u8>, String> {
+    use openssl::pkcs12::Pkcs12;
+    use openssl::pkey::PKey;
+    use openssl::stack::Stack;
+    use openssl::x509::X509;
+
+    let mut chain = X509::stack_from_pem(cert).map_err(|e| e.to_string())?;
+    if chain.is_empty() {
+        return Err("no certificate found".to_owned());
+    }
+    let leaf = chain.remove(0);
+    let key = PKey::private_key_from_pem(key).map_err(|_| "no private key found".to_owned())?;
+    let mut ca = Stack::new().map_err(|e| e.to_string())?;
+    for cert in chain {
+        ca.push(cert).map_err(|e| e.to_string())?;
+    }
+    Pkcs12::builder()
+        .name("hurl")
+        .pkey(&key)
+        .cert(&leaf)
+        .ca(ca)
+        .build2("")
+        .and_then(|p12| p12.to_der())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "ios"))]
+fn pem_to_pkcs12(_cert: &[u8], _key: &[u8]) -> Result<Vec<u8>, String> {
+    Err("PEM client certificates are not supported on this platform, use a .p12 file".to_owned())
+}